@@ -12,6 +12,9 @@
 // limitations under the License.
 
 use chrono::prelude::*;
+use chrono_tz::Tz;
+
+use errors::*;
 
 /// Account information.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -23,6 +26,42 @@ pub struct Account {
     pro_expires_time: DateTime<Utc>,
 }
 
+impl Account {
+    /// The name of the user.
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    /// The email address of the user.
+    pub fn email(&self) -> &str {
+        &self.email
+    }
+
+    /// The name of the timezone in the tz database, e.g. `Asia/Shanghai`.
+    pub fn timezone(&self) -> &str {
+        &self.timezone
+    }
+
+    /// When the account was registered.
+    pub fn register_time(&self) -> DateTime<Utc> {
+        self.register_time
+    }
+
+    /// When the Pro subscription expires.
+    pub fn pro_expires_time(&self) -> DateTime<Utc> {
+        self.pro_expires_time
+    }
+
+    /// The `timezone` parsed as a tz database zone.
+    ///
+    /// Fails with `ErrorKind::UnknownTimezone` if there is no such zone.
+    pub fn tz(&self) -> Result<Tz, Error> {
+        self.timezone
+            .parse()
+            .map_err(|_| ErrorKind::UnknownTimezone(self.timezone.clone()).into())
+    }
+}
+
 impl ::std::fmt::Display for Account {
     fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
         use serde_json::to_string_pretty;
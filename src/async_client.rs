@@ -0,0 +1,330 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::mem;
+use std::rc::Rc;
+use std::fmt::Debug;
+
+use uuid::Uuid;
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use futures::{future, Future, Stream};
+use tokio_core::reactor::Handle;
+
+use reqwest::{Method, Url};
+use reqwest::header::Headers;
+use reqwest::unstable::async::{Client as ReqwestClient, Decoder};
+
+use errors::*;
+use account::Account;
+use pomo::{Pomo, PomoParameter};
+use todo::{Todo, SubTodo, TodoParameter};
+use client::{Api, ClientBuilder, check, decode};
+use transport::{HttpRequest, HttpResponse};
+use ratelimit::RateLimiter;
+use patch::{PomoPatch, TodoPatch, SubTodoPatch};
+use validate::ensure_valid;
+
+/// A boxed `Future` resolved by the [`AsyncClient`](struct.AsyncClient.html).
+pub type ApiFuture<T> = Box<Future<Item = T, Error = Error>>;
+
+/// An async `Client` to communicate with Pomotodo server.
+///
+/// This client is only available with the `async` feature enabled. It
+/// exposes the same endpoints as the blocking [`Client`](struct.Client.html),
+/// but every method returns a future to be driven by a `tokio_core` reactor.
+///
+/// # Example
+///
+/// ```rust,no_run
+/// # extern crate pomotodo;
+/// # extern crate tokio_core;
+/// # use pomotodo::AsyncClient;
+/// # use tokio_core::reactor::Core;
+/// #
+/// # fn main() {
+/// let mut core = Core::new().unwrap();
+/// let client = AsyncClient::new("YOUR_ACCESS_TOKEN", &core.handle());
+/// let account = core.run(client.account()).unwrap();
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct AsyncClient {
+    api: Api,
+    transport: Rc<AsyncTransport>,
+    limiter: Option<RateLimiter>,
+}
+
+/// The async counterpart of [`Transport`](trait.Transport.html).
+pub trait AsyncTransport: Debug {
+    /// Sends the `request`, resolving to the whole response.
+    fn send(&self, request: HttpRequest) -> ApiFuture<HttpResponse>;
+}
+
+/// The default [`AsyncTransport`](trait.AsyncTransport.html),
+/// backed by reqwest's async client.
+#[derive(Debug, Clone)]
+pub struct ReqwestAsyncTransport {
+    inner: ReqwestClient,
+}
+
+impl ReqwestAsyncTransport {
+    /// Constructs a new `ReqwestAsyncTransport` running on `handle`.
+    pub fn new(handle: &Handle) -> ReqwestAsyncTransport {
+        ReqwestAsyncTransport { inner: ReqwestClient::new(handle) }
+    }
+}
+
+impl From<ReqwestClient> for ReqwestAsyncTransport {
+    fn from(client: ReqwestClient) -> ReqwestAsyncTransport {
+        ReqwestAsyncTransport { inner: client }
+    }
+}
+
+impl AsyncTransport for ReqwestAsyncTransport {
+    fn send(&self, request: HttpRequest) -> ApiFuture<HttpResponse> {
+        let mut headers = Headers::new();
+        for (name, value) in request.headers {
+            headers.set_raw(name, value);
+        }
+
+        let mut builder = self.inner.request(request.method, request.url);
+        builder.headers(headers);
+        if let Some(body) = request.body {
+            builder.body(body);
+        }
+
+        let future = builder
+            .send()
+            .and_then(|mut resp| {
+                let status = resp.status().as_u16();
+                let headers: Vec<(String, String)> = resp.headers()
+                    .iter()
+                    .map(|header| (header.name().to_owned(), header.value_string()))
+                    .collect();
+
+                mem::replace(resp.body_mut(), Decoder::empty())
+                    .fold(Vec::new(), |mut body, chunk| {
+                        body.extend_from_slice(&chunk);
+                        Ok::<_, ::reqwest::Error>(body)
+                    })
+                    .map(move |body| {
+                             HttpResponse {
+                                 status: status,
+                                 headers: headers,
+                                 body: body,
+                             }
+                         })
+            })
+            .map_err(Error::from);
+
+        Box::new(future)
+    }
+}
+
+impl ClientBuilder {
+    /// Build an [`AsyncClient`](struct.AsyncClient.html) running on `handle`.
+    ///
+    /// The [`Transport`](trait.Transport.html) and the
+    /// [`RetryPolicy`](struct.RetryPolicy.html) set on the builder are only
+    /// used by the blocking `Client` and ignored here, every request is sent
+    /// once. A [`RateLimiter`](struct.RateLimiter.html) is honoured, but
+    /// waiting for a token blocks the reactor thread.
+    pub fn finish_async(self, handle: &Handle) -> Result<AsyncClient, Error> {
+        self.finish_async_with(ReqwestAsyncTransport::new(handle))
+    }
+
+    /// Build an [`AsyncClient`](struct.AsyncClient.html) sending its
+    /// requests through `transport`, see
+    /// [`finish_async`](#method.finish_async) for the builder settings used.
+    pub fn finish_async_with<T>(self, transport: T) -> Result<AsyncClient, Error>
+        where T: AsyncTransport + 'static
+    {
+        Ok(AsyncClient {
+               api: self.api()?,
+               transport: Rc::new(transport),
+               limiter: self.limiter,
+           })
+    }
+}
+
+impl AsyncClient {
+    /// Constructs a new `AsyncClient` running on `handle`.
+    pub fn new<T>(token: T, handle: &Handle) -> AsyncClient
+        where T: Into<String>
+    {
+        ::client::Client::builder(token)
+            .finish_async(handle)
+            .expect("the default base URL is valid")
+    }
+
+    /// Request for the `Account`'s profile.
+    pub fn account(&self) -> ApiFuture<Account> {
+        self.get(self.api.url("account"))
+    }
+
+    /// Request for the `Pomo` specified by `uuid`.
+    pub fn pomo<U: Into<Uuid>>(&self, uuid: U) -> ApiFuture<Pomo> {
+        self.get(self.api.url(&format!("pomos/{}", uuid.into())))
+    }
+
+    /// Request for all `Pomo` that matched the `param`.
+    pub fn pomos(&self, param: PomoParameter) -> ApiFuture<Vec<Pomo>> {
+        self.get(self.api.query_url("pomos", &param.query()))
+    }
+
+    /// Submit a new `Pomo` to server, rejecting an invalid one
+    /// without any request like the blocking `Client` does.
+    pub fn submit_pomo(&self, pomo: &Pomo) -> ApiFuture<Pomo> {
+        self.post(ensure_valid(pomo).and_then(|_| self.api.url("pomos")), pomo)
+    }
+
+    /// Request server to update an existed `Pomo`, see
+    /// [`Client::update_pomo`](struct.Client.html#method.update_pomo).
+    pub fn update_pomo<U: Into<Uuid>>(&self, uuid: U, patch: &PomoPatch) -> ApiFuture<Pomo> {
+        let path = format!("pomos/{}", uuid.into());
+        self.patch(ensure_valid(patch).and_then(|_| self.api.url(&path)), patch)
+    }
+
+    /// Requests server to delete the `Pomo` specified by `uuid`.
+    pub fn delete_pomo<U: Into<Uuid>>(&self, uuid: U) -> ApiFuture<()> {
+        self.delete(self.api.url(&format!("pomos/{}", uuid.into())))
+    }
+
+    /// Request for the `Todo` specified by `uuid`.
+    pub fn todo<U: Into<Uuid>>(&self, uuid: U) -> ApiFuture<Todo> {
+        self.get(self.api.url(&format!("todos/{}", uuid.into())))
+    }
+
+    /// Request for all `Todo` that match with the `param`.
+    pub fn todos(&self, param: TodoParameter) -> ApiFuture<Vec<Todo>> {
+        self.get(self.api.query_url("todos", &param.query()))
+    }
+
+    /// Requests server to creates a new `Todo`.
+    pub fn create_todo(&self, todo: &Todo) -> ApiFuture<Todo> {
+        self.post(ensure_valid(todo).and_then(|_| self.api.url("todos")), todo)
+    }
+
+    /// Request server to update an existed `Todo`, see
+    /// [`Client::update_todo`](struct.Client.html#method.update_todo).
+    pub fn update_todo<U: Into<Uuid>>(&self, uuid: U, patch: &TodoPatch) -> ApiFuture<Todo> {
+        let path = format!("todos/{}", uuid.into());
+        self.patch(ensure_valid(patch).and_then(|_| self.api.url(&path)), patch)
+    }
+
+    /// Requests server to delete the `Todo` specified by `uuid`.
+    pub fn delete_todo<U: Into<Uuid>>(&self, uuid: U) -> ApiFuture<()> {
+        self.delete(self.api.url(&format!("todos/{}", uuid.into())))
+    }
+
+    /// Request for the `SubTodo` owned by `parent` and has the `uuid`.
+    pub fn subtodo<U: Into<Uuid>>(&self, parent: U, uuid: U) -> ApiFuture<SubTodo> {
+        let path = format!("todos/{}/sub_todos/{}", parent.into(), uuid.into());
+        self.get(self.api.url(&path))
+    }
+
+    /// Request for all `SubTodo` owned by `parent`.
+    pub fn subtodos<U: Into<Uuid>>(&self, parent: U) -> ApiFuture<Vec<SubTodo>> {
+        self.get(self.api.url(&format!("todos/{}/sub_todos", parent.into())))
+    }
+
+    /// Requests server to create a new `SubTodo` under the
+    /// [`Todo`](struct.Todo.html) specified by `parent`.
+    pub fn create_subtodo<U: Into<Uuid>>(&self, parent: U, sub_todo: &SubTodo) -> ApiFuture<SubTodo> {
+        let path = format!("todos/{}/sub_todos", parent.into());
+        self.post(ensure_valid(sub_todo).and_then(|_| self.api.url(&path)), sub_todo)
+    }
+
+    /// Request server to update an existed `SubTodo`, see
+    /// [`Client::update_subtodo`](struct.Client.html#method.update_subtodo).
+    pub fn update_subtodo<U: Into<Uuid>>(&self,
+                                         parent: U,
+                                         uuid: U,
+                                         patch: &SubTodoPatch)
+                                         -> ApiFuture<SubTodo> {
+        let path = format!("todos/{}/sub_todos/{}", parent.into(), uuid.into());
+        self.patch(ensure_valid(patch).and_then(|_| self.api.url(&path)), patch)
+    }
+
+    /// Requests server to delete the [`SubTodo`](struct.Todo.html)
+    /// owned by `parent`and had the `uuid`.
+    pub fn delete_subtodo<U: Into<Uuid>>(&self, parent: U, uuid: U) -> ApiFuture<()> {
+        let path = format!("todos/{}/sub_todos/{}", parent.into(), uuid.into());
+        self.delete(self.api.url(&path))
+    }
+
+    /// Makes a request with an optional json body through the `AsyncTransport`.
+    fn request<I>(&self,
+                  method: Method,
+                  url: Result<Url, Error>,
+                  json: Option<&I>)
+                  -> ApiFuture<HttpResponse>
+        where I: Serialize
+    {
+        let url = match url {
+            Ok(url) => url,
+            Err(e) => return Box::new(future::err(e)),
+        };
+
+        let request = match self.api.request(method, url.clone(), json) {
+            Ok(request) => request,
+            Err(e) => return Box::new(future::err(e)),
+        };
+
+        let transport = self.transport.clone();
+        let limiter = self.limiter.clone();
+        let future = future::lazy(move || {
+                                      if let Some(ref limiter) = limiter {
+                                          limiter.acquire();
+                                      }
+                                      transport.send(request).map(move |resp| (limiter, resp))
+                                  })
+                .and_then(move |(limiter, resp)| {
+                              if let Some(ref limiter) = limiter {
+                                  limiter.observe(&resp);
+                              }
+                              check(&url, resp)
+                          });
+
+        Box::new(future)
+    }
+
+    /// Convenience method to make a GET request to a URL.
+    fn get<O>(&self, url: Result<Url, Error>) -> ApiFuture<O>
+        where O: DeserializeOwned + 'static
+    {
+        Box::new(self.request::<()>(Method::Get, url, None).and_then(decode))
+    }
+
+    /// Convenience method to make a POST request with json body to a URL.
+    fn post<I, O>(&self, url: Result<Url, Error>, json: &I) -> ApiFuture<O>
+        where I: Serialize, O: DeserializeOwned + 'static
+    {
+        Box::new(self.request(Method::Post, url, Some(json)).and_then(decode))
+    }
+
+    /// Convenience method to make a PATCH request with json body to a URL.
+    fn patch<I, O>(&self, url: Result<Url, Error>, json: &I) -> ApiFuture<O>
+        where I: Serialize, O: DeserializeOwned + 'static
+    {
+        Box::new(self.request(Method::Patch, url, Some(json)).and_then(decode))
+    }
+
+    /// Convenience method to make a DELETE request to a URL.
+    fn delete(&self, url: Result<Url, Error>) -> ApiFuture<()> {
+        Box::new(self.request::<()>(Method::Delete, url, None).map(|_| ()))
+    }
+}
@@ -0,0 +1,557 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The `pomotodo` command-line client, built with the `cli` feature.
+//!
+//! The access token is read from `--token` or the `POMOTODO_TOKEN`
+//! environment variable, the global options go before the subcommand:
+//!
+//! ```text
+//! pomotodo --format json pomo list --started-after 2017-01-01T00:00:00Z
+//! ```
+//!
+//! With `--outbox` or `POMOTODO_OUTBOX`, a pomo recorded by `start` while
+//! offline is queued in that file and submitted by the next command. That
+//! replay only warns on failure, `pomotodo outbox replay` reports it as an
+//! error instead.
+
+#![deny(warnings)]
+
+extern crate uuid;
+extern crate serde;
+extern crate chrono;
+extern crate serde_json;
+extern crate pomotodo;
+extern crate ctrlc;
+#[macro_use]
+extern crate clap;
+
+mod output;
+mod parse;
+mod start;
+
+use std::env;
+use std::path::PathBuf;
+use std::process;
+
+use clap::{App, AppSettings, Arg, ArgGroup, ArgMatches, SubCommand};
+use chrono::prelude::*;
+use chrono::Duration;
+
+use pomotodo::errors::*;
+use pomotodo::{Client, Pomo, Todo, SubTodo, PomoParameter, TodoParameter, RepeatType};
+use pomotodo::{Field, PomoPatch, TodoPatch, SubTodoPatch};
+use pomotodo::outbox::Outbox;
+
+use output::Format;
+use parse::{uuid_arg, time_arg, duration_arg, value_arg};
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("error: {}", e);
+        for cause in e.iter().skip(1) {
+            eprintln!("caused by: {}", cause);
+        }
+        process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Error> {
+    let matches = app().get_matches();
+    let format = matches.value_of("format").unwrap_or("table").parse::<Format>()?;
+    let client = client(&matches)?;
+    if let ("outbox", Some(subcommand)) = matches.subcommand() {
+        return outbox(&client, outbox_path(&matches), subcommand);
+    }
+
+    let mut outbox = match outbox_path(&matches).map(Outbox::open) {
+        Some(Ok(outbox)) => Some(outbox),
+        Some(Err(e)) => {
+            eprintln!("warning: ignored the outbox: {}", e);
+            None
+        }
+        None => None,
+    };
+    if let Some(ref mut outbox) = outbox {
+        if let Err(e) = replay(&client, outbox) {
+            eprintln!("warning: failed to send the queued writes: {}", e);
+        }
+    }
+
+    match matches.subcommand() {
+        ("account", Some(_)) => output::print_one(format, &client.account()?, output::ACCOUNT),
+        ("pomo", Some(matches)) => pomo(&client, format, matches),
+        ("todo", Some(matches)) => todo(&client, format, matches),
+        ("subtodo", Some(matches)) => subtodo(&client, format, matches),
+        ("start", Some(matches)) => start::run(&client, outbox.as_mut(), format, matches),
+        _ => unreachable!(),
+    }
+}
+
+/// Builds the `Client` from `--token` or `POMOTODO_TOKEN`, and `--base-url`.
+fn client(matches: &ArgMatches) -> Result<Client, Error> {
+    let token = match matches.value_of("token") {
+        Some(token) => token.to_owned(),
+        None => {
+            env::var("POMOTODO_TOKEN")
+                .map_err(|_| Error::from("missing access token, set --token or POMOTODO_TOKEN"))?
+        }
+    };
+
+    let mut builder = Client::builder(token);
+    if let Some(base_url) = matches.value_of("base-url") {
+        builder.base_url(base_url);
+    }
+    builder.finish()
+}
+
+/// The outbox file from `--outbox` or `POMOTODO_OUTBOX`, if any.
+fn outbox_path(matches: &ArgMatches) -> Option<PathBuf> {
+    matches
+        .value_of_os("outbox")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("POMOTODO_OUTBOX").map(PathBuf::from))
+}
+
+/// Sends what was queued while offline, reporting on stderr.
+fn replay(client: &Client, outbox: &mut Outbox) -> Result<(), Error> {
+    if outbox.is_empty() {
+        return Ok(());
+    }
+
+    let report = outbox.replay(client)?;
+    if report.sent > 0 {
+        eprintln!("sent {} queued write(s)", report.sent);
+    }
+    for &(ref op, ref e) in &report.rejected {
+        eprintln!("dropped a queued write refused by server: {}\n  {:?}", e, op);
+    }
+    if !outbox.is_empty() {
+        eprintln!("{} write(s) still queued, server is unavailable", outbox.len());
+    }
+    Ok(())
+}
+
+fn outbox(client: &Client, path: Option<PathBuf>, matches: &ArgMatches) -> Result<(), Error> {
+    let path = path.ok_or("missing outbox, set --outbox or POMOTODO_OUTBOX")?;
+    match matches.subcommand() {
+        ("replay", Some(_)) => replay(client, &mut Outbox::open(path)?),
+        _ => unreachable!(),
+    }
+}
+
+fn pomo(client: &Client, format: Format, matches: &ArgMatches) -> Result<(), Error> {
+    match matches.subcommand() {
+        ("list", Some(matches)) => {
+            let mut param = PomoParameter::default();
+            param
+                .with_abandoned(matches.is_present("abandoned"))
+                .with_manual(matches.is_present("manual"));
+            if let Some(time) = time_arg(matches, "started-after")? {
+                param.with_started_later(time);
+            }
+            if let Some(time) = time_arg(matches, "started-before")? {
+                param.with_started_earlier(time);
+            }
+            if let Some(time) = time_arg(matches, "ended-after")? {
+                param.with_ended_later(time);
+            }
+            if let Some(time) = time_arg(matches, "ended-before")? {
+                param.with_ended_earlier(time);
+            }
+
+            let pomos = if matches.is_present("all") {
+                client.pomos_iter(param).collect::<Result<Vec<_>, _>>()?
+            } else {
+                client.pomos(param)?
+            };
+            output::print_list(format, &pomos, output::POMO)
+        }
+        ("show", Some(matches)) => {
+            let pomo = client.pomo(uuid_arg(matches, "uuid")?)?;
+            output::print_one(format, &pomo, output::POMO)
+        }
+        ("add", Some(matches)) => {
+            let length = duration_arg(matches, "length")?;
+            let ended_at = time_arg(matches, "ended-at")?;
+            let started_at = match time_arg(matches, "started-at")? {
+                Some(started_at) => started_at,
+                None => {
+                    ended_at.unwrap_or_else(Utc::now)
+                        .checked_sub_signed(length.unwrap_or_else(Duration::zero))
+                        .ok_or("the length is out of range")?
+                }
+            };
+
+            let mut builder = Pomo::builder();
+            builder
+                .description(matches.value_of("description").unwrap_or_default())
+                .started_at(started_at)
+                .abandoned(matches.is_present("abandoned"));
+            if let Some(length) = length {
+                builder.length(length.num_seconds() as u64);
+            }
+            if let Some(ended_at) = ended_at {
+                builder.ended_at(ended_at);
+            }
+
+            let pomo = client.submit_pomo(&builder.finish()?)?;
+            output::print_one(format, &pomo, output::POMO)
+        }
+        ("edit", Some(matches)) => {
+            let patch = PomoPatch::description(matches.value_of("description").unwrap_or_default());
+            let pomo = client.update_pomo(uuid_arg(matches, "uuid")?, &patch)?;
+            output::print_one(format, &pomo, output::POMO)
+        }
+        ("rm", Some(matches)) => client.delete_pomo(uuid_arg(matches, "uuid")?),
+        _ => unreachable!(),
+    }
+}
+
+fn todo(client: &Client, format: Format, matches: &ArgMatches) -> Result<(), Error> {
+    match matches.subcommand() {
+        ("list", Some(matches)) => {
+            let mut param = TodoParameter::default();
+            param.with_completed(matches.is_present("completed"));
+            if let Some(time) = time_arg(matches, "completed-after")? {
+                param.with_completed_later(time);
+            }
+            if let Some(time) = time_arg(matches, "completed-before")? {
+                param.with_completed_earlier(time);
+            }
+
+            let todos = if matches.is_present("all") {
+                client.todos_iter(param).collect::<Result<Vec<_>, _>>()?
+            } else {
+                client.todos(param)?
+            };
+            output::print_list(format, &todos, output::TODO)
+        }
+        ("show", Some(matches)) => {
+            let todo = client.todo(uuid_arg(matches, "uuid")?)?;
+            output::print_one(format, &todo, output::TODO)
+        }
+        ("add", Some(matches)) => {
+            let mut builder = Todo::builder();
+            builder.description(matches.value_of("description").unwrap_or_default());
+            if let Some(notice) = matches.value_of("notice") {
+                builder.notice(notice);
+            }
+            if matches.is_present("pin") {
+                builder.pin(true);
+            }
+            if let Some(repeat) = value_arg::<RepeatType>(matches, "repeat")? {
+                builder.repeat_type(repeat);
+            }
+            if let Some(time) = time_arg(matches, "remind-at")? {
+                builder.remind_time(time);
+            }
+            if let Some(count) = value_arg(matches, "estimate")? {
+                builder.estimated_pomo_count(count);
+            }
+
+            let todo = client.create_todo(&builder.finish()?)?;
+            output::print_one(format, &todo, output::TODO)
+        }
+        ("done", Some(matches)) => {
+            let patch = TodoPatch {
+                completed: Field::Set(true),
+                completed_at: Field::Set(Utc::now()),
+                ..Default::default()
+            };
+            let todo = client.update_todo(uuid_arg(matches, "uuid")?, &patch)?;
+            output::print_one(format, &todo, output::TODO)
+        }
+        ("edit", Some(matches)) => {
+            let patch = todo_patch(matches)?;
+            if patch.is_empty() {
+                return Err("nothing to edit".into());
+            }
+
+            let todo = client.update_todo(uuid_arg(matches, "uuid")?, &patch)?;
+            output::print_one(format, &todo, output::TODO)
+        }
+        ("rm", Some(matches)) => client.delete_todo(uuid_arg(matches, "uuid")?),
+        _ => unreachable!(),
+    }
+}
+
+/// Collects the `TodoPatch` of `todo edit`, `--clear` wins over the values.
+fn todo_patch(matches: &ArgMatches) -> Result<TodoPatch, Error> {
+    let mut patch = TodoPatch::default();
+
+    if let Some(desc) = matches.value_of("description") {
+        patch.description = Field::Set(desc.to_owned());
+    }
+    if let Some(notice) = matches.value_of("notice") {
+        patch.notice = Field::Set(notice.to_owned());
+    }
+    if let Some(pin) = value_arg(matches, "pin")? {
+        patch.pin = Field::Set(pin);
+    }
+    if let Some(completed) = value_arg(matches, "completed")? {
+        patch.completed = Field::Set(completed);
+    }
+    if let Some(repeat) = value_arg(matches, "repeat")? {
+        patch.repeat_type = Field::Set(repeat);
+    }
+    if let Some(time) = time_arg(matches, "remind-at")? {
+        patch.remind_time = Field::Set(time);
+    }
+    if let Some(count) = value_arg(matches, "estimate")? {
+        patch.estimated_pomo_count = Field::Set(count);
+    }
+    if let Some(count) = value_arg(matches, "costed")? {
+        patch.costed_pomo_count = Field::Set(count);
+    }
+
+    for field in matches.values_of("clear").into_iter().flat_map(|fields| fields) {
+        match field {
+            "notice" => patch.notice = Field::Clear,
+            "completed_at" => patch.completed_at = Field::Clear,
+            "repeat_type" => patch.repeat_type = Field::Clear,
+            "remind_time" => patch.remind_time = Field::Clear,
+            "estimated_pomo_count" => patch.estimated_pomo_count = Field::Clear,
+            "costed_pomo_count" => patch.costed_pomo_count = Field::Clear,
+            _ => unreachable!(),
+        }
+    }
+
+    Ok(patch)
+}
+
+fn subtodo(client: &Client, format: Format, matches: &ArgMatches) -> Result<(), Error> {
+    match matches.subcommand() {
+        ("list", Some(matches)) => {
+            let sub_todos = client.subtodos(uuid_arg(matches, "parent")?)?;
+            output::print_list(format, &sub_todos, output::SUB_TODO)
+        }
+        ("show", Some(matches)) => {
+            let sub_todo = client
+                .subtodo(uuid_arg(matches, "parent")?, uuid_arg(matches, "uuid")?)?;
+            output::print_one(format, &sub_todo, output::SUB_TODO)
+        }
+        ("add", Some(matches)) => {
+            let mut builder = SubTodo::builder();
+            builder.description(matches.value_of("description").unwrap_or_default());
+
+            let sub_todo = client.create_subtodo(uuid_arg(matches, "parent")?, &builder.finish()?)?;
+            output::print_one(format, &sub_todo, output::SUB_TODO)
+        }
+        ("done", Some(matches)) => {
+            let patch = SubTodoPatch {
+                completed: Field::Set(true),
+                completed_at: Field::Set(Utc::now()),
+                ..Default::default()
+            };
+            let sub_todo = client
+                .update_subtodo(uuid_arg(matches, "parent")?, uuid_arg(matches, "uuid")?, &patch)?;
+            output::print_one(format, &sub_todo, output::SUB_TODO)
+        }
+        ("edit", Some(matches)) => {
+            let desc = matches.value_of("description").unwrap_or_default();
+            let patch = SubTodoPatch {
+                description: Field::Set(desc.to_owned()),
+                ..Default::default()
+            };
+            let sub_todo = client
+                .update_subtodo(uuid_arg(matches, "parent")?, uuid_arg(matches, "uuid")?, &patch)?;
+            output::print_one(format, &sub_todo, output::SUB_TODO)
+        }
+        ("rm", Some(matches)) => {
+            client.delete_subtodo(uuid_arg(matches, "parent")?, uuid_arg(matches, "uuid")?)
+        }
+        _ => unreachable!(),
+    }
+}
+
+fn app() -> App<'static, 'static> {
+    App::new("pomotodo")
+        .version(crate_version!())
+        .about("Command-line client of Pomotodo")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(Arg::with_name("token")
+                 .long("token")
+                 .value_name("TOKEN")
+                 .help("The access token, defaults to $POMOTODO_TOKEN"))
+        .arg(Arg::with_name("base-url")
+                 .long("base-url")
+                 .value_name("URL")
+                 .help("The base URL of the server"))
+        .arg(Arg::with_name("outbox")
+                 .long("outbox")
+                 .value_name("FILE")
+                 .help("Queue the pomos recorded offline in FILE, defaults to $POMOTODO_OUTBOX"))
+        .arg(Arg::with_name("format")
+                 .long("format")
+                 .short("f")
+                 .value_name("FORMAT")
+                 .possible_values(&["table", "json", "ndjson"])
+                 .default_value("table")
+                 .help("The output format"))
+        .subcommand(SubCommand::with_name("account").about("Show the account"))
+        .subcommand(pomo_app())
+        .subcommand(todo_app())
+        .subcommand(subtodo_app())
+        .subcommand(SubCommand::with_name("start")
+                        .about("Run a pomo in the foreground and record it, Ctrl-C abandons it")
+                        .arg(description().required(true))
+                        .arg(option("length", "LENGTH", "The length, e.g. 25m")
+                                 .default_value("25m"))
+                        .arg(option("todo", "UUID", "The todo to count the pomo for")))
+        .subcommand(SubCommand::with_name("outbox")
+                        .about("Manage the writes queued while offline")
+                        .setting(AppSettings::SubcommandRequiredElseHelp)
+                        .subcommand(SubCommand::with_name("replay")
+                                        .about("Send the queued writes now")))
+}
+
+fn pomo_app() -> App<'static, 'static> {
+    SubCommand::with_name("pomo")
+        .about("Manage the pomos")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("list")
+                        .about("List the pomos, latest first")
+                        .arg(flag("abandoned", "List the abandoned pomos"))
+                        .arg(flag("manual", "List the manually added pomos"))
+                        .arg(time("started-after", "Started later than TIME"))
+                        .arg(time("started-before", "Started earlier than TIME"))
+                        .arg(time("ended-after", "Ended later than TIME"))
+                        .arg(time("ended-before", "Ended earlier than TIME"))
+                        .arg(flag("all", "Walk through every page")))
+        .subcommand(SubCommand::with_name("show").about("Show a pomo").arg(uuid_param("uuid")))
+        .subcommand(SubCommand::with_name("add")
+                        .about("Add a finished pomo")
+                        .arg(description().required(true))
+                        .arg(time("started-at", "Started at TIME, defaults to LENGTH ago"))
+                        .arg(time("ended-at", "Ended at TIME"))
+                        .arg(Arg::with_name("length")
+                                 .long("length")
+                                 .value_name("LENGTH")
+                                 .help("The length, e.g. 25m"))
+                        .arg(flag("abandoned", "Mark the pomo abandoned"))
+                        .group(ArgGroup::with_name("span")
+                                   .args(&["started-at", "length"])
+                                   .multiple(true)
+                                   .required(true)))
+        .subcommand(SubCommand::with_name("edit")
+                        .about("Edit the description of a pomo")
+                        .arg(uuid_param("uuid"))
+                        .arg(description().required(true)))
+        .subcommand(SubCommand::with_name("rm").about("Delete a pomo").arg(uuid_param("uuid")))
+}
+
+fn todo_app() -> App<'static, 'static> {
+    SubCommand::with_name("todo")
+        .about("Manage the todos")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("list")
+                        .about("List the todos")
+                        .arg(flag("completed", "List the completed todos"))
+                        .arg(time("completed-after", "Completed later than TIME"))
+                        .arg(time("completed-before", "Completed earlier than TIME"))
+                        .arg(flag("all", "Walk through every page")))
+        .subcommand(SubCommand::with_name("show").about("Show a todo").arg(uuid_param("uuid")))
+        .subcommand(SubCommand::with_name("add")
+                        .about("Add a todo")
+                        .arg(description().required(true))
+                        .arg(option("notice", "NOTICE", "The notice"))
+                        .arg(flag("pin", "Pin the todo"))
+                        .arg(repeat())
+                        .arg(time("remind-at", "Remind at TIME"))
+                        .arg(option("estimate", "COUNT", "The estimated pomo count")))
+        .subcommand(SubCommand::with_name("done").about("Complete a todo").arg(uuid_param("uuid")))
+        .subcommand(SubCommand::with_name("edit")
+                        .about("Edit a todo")
+                        .arg(uuid_param("uuid"))
+                        .arg(option("description", "DESCRIPTION", "The description"))
+                        .arg(option("notice", "NOTICE", "The notice"))
+                        .arg(option("pin", "BOOL", "Whether the todo is pinned"))
+                        .arg(option("completed", "BOOL", "Whether the todo is completed"))
+                        .arg(repeat())
+                        .arg(time("remind-at", "Remind at TIME"))
+                        .arg(option("estimate", "COUNT", "The estimated pomo count"))
+                        .arg(option("costed", "COUNT", "The costed pomo count"))
+                        .arg(Arg::with_name("clear")
+                                 .long("clear")
+                                 .value_name("FIELD")
+                                 .multiple(true)
+                                 .number_of_values(1)
+                                 .possible_values(&["notice",
+                                                    "completed_at",
+                                                    "repeat_type",
+                                                    "remind_time",
+                                                    "estimated_pomo_count",
+                                                    "costed_pomo_count"])
+                                 .help("Clear the FIELD")))
+        .subcommand(SubCommand::with_name("rm").about("Delete a todo").arg(uuid_param("uuid")))
+}
+
+fn subtodo_app() -> App<'static, 'static> {
+    SubCommand::with_name("subtodo")
+        .about("Manage the sub-todos of a todo")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(SubCommand::with_name("list")
+                        .about("List the sub-todos")
+                        .arg(uuid_param("parent")))
+        .subcommand(SubCommand::with_name("show")
+                        .about("Show a sub-todo")
+                        .arg(uuid_param("parent"))
+                        .arg(uuid_param("uuid")))
+        .subcommand(SubCommand::with_name("add")
+                        .about("Add a sub-todo")
+                        .arg(uuid_param("parent"))
+                        .arg(description().required(true)))
+        .subcommand(SubCommand::with_name("done")
+                        .about("Complete a sub-todo")
+                        .arg(uuid_param("parent"))
+                        .arg(uuid_param("uuid")))
+        .subcommand(SubCommand::with_name("edit")
+                        .about("Edit the description of a sub-todo")
+                        .arg(uuid_param("parent"))
+                        .arg(uuid_param("uuid"))
+                        .arg(description().required(true)))
+        .subcommand(SubCommand::with_name("rm")
+                        .about("Delete a sub-todo")
+                        .arg(uuid_param("parent"))
+                        .arg(uuid_param("uuid")))
+}
+
+fn uuid_param(name: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name(name).required(true).help("A UUID")
+}
+
+fn description() -> Arg<'static, 'static> {
+    Arg::with_name("description").help("The description")
+}
+
+fn flag(name: &'static str, help: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name(name).long(name).help(help)
+}
+
+fn option(name: &'static str, value: &'static str, help: &'static str) -> Arg<'static, 'static> {
+    Arg::with_name(name).long(name).value_name(value).help(help)
+}
+
+fn time(name: &'static str, help: &'static str) -> Arg<'static, 'static> {
+    option(name, "TIME", help)
+}
+
+fn repeat() -> Arg<'static, 'static> {
+    option("repeat", "TYPE", "The repeat type").possible_values(&["none",
+                                                                  "each_day",
+                                                                  "each_week",
+                                                                  "each_two_week",
+                                                                  "each_month",
+                                                                  "each_year"])
+}
@@ -0,0 +1,120 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{self, Write};
+
+use serde::Serialize;
+use serde_json::{self, Value};
+
+use pomotodo::errors::*;
+
+/// The columns of the tables, named after the json fields.
+pub const ACCOUNT: &'static [&'static str] = &["username", "email", "timezone", "pro_expires_time"];
+pub const POMO: &'static [&'static str] = &["uuid", "started_at", "ended_at", "abandoned",
+                                            "description"];
+pub const TODO: &'static [&'static str] = &["uuid", "completed", "pin", "remind_time",
+                                            "description"];
+pub const SUB_TODO: &'static [&'static str] = &["uuid", "completed", "description"];
+
+/// The output format selected by `--format`.
+#[derive(Debug, Clone, Copy)]
+pub enum Format {
+    Table,
+    Json,
+    Ndjson,
+}
+
+impl ::std::str::FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Format, Error> {
+        match s {
+            "table" => Ok(Format::Table),
+            "json" => Ok(Format::Json),
+            "ndjson" => Ok(Format::Ndjson),
+            _ => Err(format!("unknown format `{}`", s).into()),
+        }
+    }
+}
+
+/// Prints a single record, a table shows the `columns` only.
+pub fn print_one<T: Serialize>(format: Format, item: &T, columns: &[&str]) -> Result<(), Error> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    match format {
+        Format::Table => table(&mut out, &[item], columns),
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut out, item)?;
+            out.write_all(b"\n").map_err(|e| e.into())
+        }
+        Format::Ndjson => {
+            serde_json::to_writer(&mut out, item)?;
+            out.write_all(b"\n").map_err(|e| e.into())
+        }
+    }
+}
+
+/// Prints the records as a table, a json array or one json object per line.
+pub fn print_list<T: Serialize>(format: Format,
+                                items: &[T],
+                                columns: &[&str])
+                                -> Result<(), Error> {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+
+    match format {
+        Format::Table => table(&mut out, items, columns),
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut out, items)?;
+            out.write_all(b"\n").map_err(|e| e.into())
+        }
+        Format::Ndjson => {
+            for item in items {
+                serde_json::to_writer(&mut out, item)?;
+                out.write_all(b"\n")?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn table<W: Write, T: Serialize>(out: &mut W, items: &[T], columns: &[&str]) -> Result<(), Error> {
+    let mut rows = vec![columns.iter().map(|column| column.to_uppercase()).collect::<Vec<_>>()];
+    for item in items {
+        let value = serde_json::to_value(item)?;
+        rows.push(columns.iter().map(|column| cell(value.get(*column))).collect());
+    }
+
+    let widths = (0..columns.len())
+        .map(|i| rows.iter().map(|row| row[i].chars().count()).max().unwrap_or(0))
+        .collect::<Vec<_>>();
+
+    for row in &rows {
+        let mut line = String::new();
+        for (i, cell) in row.iter().enumerate() {
+            line.push_str(&format!("{:width$}  ", cell, width = widths[i]));
+        }
+        writeln!(out, "{}", line.trim_right())?;
+    }
+
+    Ok(())
+}
+
+fn cell(value: Option<&Value>) -> String {
+    match value {
+        None | Some(&Value::Null) => "-".to_owned(),
+        Some(&Value::String(ref s)) => s.clone(),
+        Some(value) => value.to_string(),
+    }
+}
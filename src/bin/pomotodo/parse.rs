@@ -0,0 +1,110 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::Duration as StdDuration;
+
+use clap::ArgMatches;
+use uuid::Uuid;
+use chrono::prelude::*;
+use chrono::Duration;
+
+use pomotodo::errors::*;
+
+/// Parses an RFC 3339 time, e.g. `2017-01-01T09:00:00+08:00`.
+pub fn time(value: &str) -> Result<DateTime<Utc>, Error> {
+    DateTime::parse_from_rfc3339(value.trim())
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| Error::from(format!("invalid time `{}`, expected RFC 3339", value)))
+}
+
+/// Parses a duration like `25m`, `1h30m` or `90s`, a bare number is in minutes.
+///
+/// Only positive durations are accepted.
+pub fn duration(value: &str) -> Result<Duration, Error> {
+    let invalid = || Error::from(format!("invalid duration `{}`, expected e.g. 25m", value));
+
+    let value = value.trim();
+    let seconds = match value.parse::<i64>() {
+        Ok(minutes) => minutes.checked_mul(60).ok_or_else(&invalid)?,
+        Err(_) => {
+            let mut total = 0i64;
+            let mut number = String::new();
+            for c in value.chars() {
+                if c.is_digit(10) {
+                    number.push(c);
+                    continue;
+                }
+
+                let n = number.parse::<i64>().map_err(|_| invalid())?;
+                number.clear();
+                let unit = match c {
+                    'h' => 60 * 60,
+                    'm' => 60,
+                    's' => 1,
+                    _ => return Err(invalid()),
+                };
+                total = n.checked_mul(unit)
+                    .and_then(|seconds| total.checked_add(seconds))
+                    .ok_or_else(&invalid)?;
+            }
+
+            if !number.is_empty() {
+                return Err(invalid());
+            }
+            total
+        }
+    };
+
+    if seconds > 0 {
+        Duration::from_std(StdDuration::from_secs(seconds as u64)).map_err(|_| invalid())
+    } else {
+        Err(invalid())
+    }
+}
+
+/// Parses the required `name` argument as a `Uuid`.
+pub fn uuid_arg(matches: &ArgMatches, name: &str) -> Result<Uuid, Error> {
+    let value = matches.value_of(name).unwrap_or_default();
+    Uuid::parse_str(value).map_err(|_| Error::from(format!("invalid uuid `{}`", value)))
+}
+
+/// Parses the optional `name` argument as a time.
+pub fn time_arg(matches: &ArgMatches, name: &str) -> Result<Option<DateTime<Utc>>, Error> {
+    match matches.value_of(name) {
+        Some(value) => time(value).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Parses the optional `name` argument as a duration.
+pub fn duration_arg(matches: &ArgMatches, name: &str) -> Result<Option<Duration>, Error> {
+    match matches.value_of(name) {
+        Some(value) => duration(value).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Parses the optional `name` argument with `FromStr`.
+pub fn value_arg<T>(matches: &ArgMatches, name: &str) -> Result<Option<T>, Error>
+    where T: ::std::str::FromStr
+{
+    match matches.value_of(name) {
+        Some(value) => {
+            value
+                .parse()
+                .map(Some)
+                .map_err(|_| Error::from(format!("invalid value `{}` for --{}", value, name)))
+        }
+        None => Ok(None),
+    }
+}
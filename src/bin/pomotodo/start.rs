@@ -0,0 +1,134 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The foreground `pomotodo start` timer.
+
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time;
+
+use clap::ArgMatches;
+use ctrlc;
+use uuid::Uuid;
+use chrono::prelude::*;
+use chrono::{Duration, Offset};
+
+use pomotodo::errors::*;
+use pomotodo::{Client, Todo, Field, TodoPatch};
+use pomotodo::outbox::{Delivery, Outbox};
+use pomotodo::timer::{Event, Timer, TimerConfig};
+
+use output::{self, Format};
+use parse::{uuid_arg, duration_arg};
+
+/// Runs a `Pomo` with a live countdown on stderr, `Ctrl-C` abandons it.
+///
+/// The `Pomo` is submitted either way, and a finished one bumps the
+/// `costed_pomo_count` of the `--todo`. With an `outbox`, both are queued
+/// if server can not be reached.
+pub fn run(client: &Client,
+           outbox: Option<&mut Outbox>,
+           format: Format,
+           matches: &ArgMatches)
+           -> Result<(), Error> {
+    let description = matches.value_of("description").unwrap_or_default();
+    let length = duration_arg(matches, "length")?.unwrap_or_else(|| Duration::minutes(25));
+    let todo = match matches.value_of("todo") {
+        Some(_) => Some(uuid_arg(matches, "todo")?),
+        None => None,
+    };
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+    {
+        let interrupted = interrupted.clone();
+        ctrlc::set_handler(move || interrupted.store(true, Ordering::SeqCst))
+            .map_err(|e| Error::from(format!("can not handle Ctrl-C: {}", e)))?;
+    }
+
+    let mut config = TimerConfig::default();
+    config
+        .with_pomo_length(length)
+        .with_utc_offset(Local::now().offset().fix());
+    let mut timer = Timer::new(config);
+    timer.start(description, Utc::now())?;
+
+    let stderr = io::stderr();
+    let event = loop {
+        let now = Utc::now();
+        if interrupted.load(Ordering::SeqCst) {
+            break timer.abandon(now)?;
+        }
+        if let Some(event) = timer.tick(now) {
+            break event;
+        }
+
+        let remaining = timer.remaining(now).unwrap_or_else(Duration::zero);
+        let mut stderr = stderr.lock();
+        write!(stderr, "\r{}  {}", countdown(remaining), description)?;
+        stderr.flush()?;
+        thread::sleep(time::Duration::from_millis(200));
+    };
+    stderr.lock().write_all(b"\n")?;
+
+    let pomo = match event {
+        Event::Finished(pomo) | Event::Abandoned(pomo) => pomo,
+        _ => unreachable!(),
+    };
+    let finished = pomo.abandoned != Some(true);
+
+    let outbox = match outbox {
+        Some(outbox) => outbox,
+        None => {
+            let pomo = client.submit_pomo(&pomo)?;
+            if let (Some(todo), true) = (todo, finished) {
+                bump(client, todo)?;
+            }
+            return output::print_one(format, &pomo, output::POMO);
+        }
+    };
+
+    let pomo = match outbox.submit_pomo(client, &pomo)? {
+        Delivery::Sent(pomo) => pomo,
+        Delivery::Queued => {
+            eprintln!("server is unavailable, the pomo is queued in {}",
+                      outbox.path().display());
+            pomo
+        }
+    };
+    if let (Some(todo), true) = (todo, finished) {
+        outbox.bump_todo(client, todo)?;
+    }
+
+    output::print_one(format, &pomo, output::POMO)
+}
+
+/// Adds one to the `costed_pomo_count` of the `todo`, fetched after the
+/// `Pomo` since it may have changed while the `Pomo` was running.
+fn bump(client: &Client, todo: Uuid) -> Result<Todo, Error> {
+    let costed = client.todo(todo)?.costed_pomo_count.unwrap_or(0);
+
+    let patch = TodoPatch {
+        costed_pomo_count: Field::Set(costed + 1),
+        ..Default::default()
+    };
+    client.update_todo(todo, &patch)
+}
+
+/// Formats the time left as `mm:ss`, rounded up to the next second.
+fn countdown(remaining: Duration) -> String {
+    let millis = remaining.num_milliseconds();
+    let secs = (millis + 999) / 1000;
+    format!("{:02}:{:02}", secs / 60, secs % 60)
+}
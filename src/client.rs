@@ -11,26 +11,40 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::thread;
+use std::sync::Arc;
+use std::time::Duration;
+
 use uuid::Uuid;
+use chrono::prelude::*;
 
 use serde::Serialize;
 use serde::de::DeserializeOwned;
 
-use reqwest::header::Authorization;
-use reqwest::{IntoUrl, StatusCode, Method, Response};
+use serde_json;
+use reqwest::{Method, Url};
 
 use errors::*;
 use account::Account;
+use retry::RetryPolicy;
+use ratelimit::RateLimiter;
+use iter::{PomoIter, TodoIter};
+use query::Query;
+use patch::{PomoPatch, TodoPatch, SubTodoPatch};
+use validate::ensure_valid;
+use transport::{Transport, ReqwestTransport, HttpRequest, HttpResponse};
 use pomo::{Pomo, PomoParameter};
 use todo::{Todo, SubTodo, TodoParameter};
 
-const TODO_URL: &'static str = "https://api.pomotodo.com/1/todos";
-const POMO_URL: &'static str = "https://api.pomotodo.com/1/pomos";
-const INFO_URL: &'static str = "https://api.pomotodo.com/1/account";
+const BASE_URL: &'static str = "https://api.pomotodo.com";
+const API_VERSION: &'static str = "1";
 
 /// A `Client` to communicate with Pomotodo server.
 ///
-/// The `Client` holds the access token and a `reqwest::Client`.
+/// The `Client` holds the access token, the root URL of the API and the
+/// [`Transport`](trait.Transport.html) sending its requests, which is a
+/// `reqwest::Client` by default. Use [`ClientBuilder`](struct.ClientBuilder.html) to
+/// point it at another server, e.g. a local mock or a staging deployment.
 ///
 /// # Example
 ///
@@ -44,16 +58,46 @@ const INFO_URL: &'static str = "https://api.pomotodo.com/1/account";
 /// ```
 #[derive(Debug, Clone)]
 pub struct Client {
+    api: Api,
+    transport: Arc<Transport>,
+    retry: RetryPolicy,
+    limiter: Option<RateLimiter>,
+}
+
+/// A builder to configure a [`Client`](struct.Client.html).
+///
+/// # Example
+///
+/// ```rust
+/// # use pomotodo::Client;
+/// #
+/// # fn run() -> Result<(), pomotodo::errors::Error> {
+/// let mut builder = Client::builder("YOUR_ACCESS_TOKEN");
+/// builder.base_url("http://localhost:8080").api_version("1");
+/// let client = builder.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct ClientBuilder {
     token: String,
-    inner: ::reqwest::Client,
+    base_url: String,
+    api_version: String,
+    transport: Option<Arc<Transport>>,
+    retry: RetryPolicy,
+    pub(crate) limiter: Option<RateLimiter>,
+}
+
+/// The access token and root URL shared by the blocking and async clients.
+#[derive(Debug, Clone)]
+pub(crate) struct Api {
+    token: String,
+    root: Url,
 }
 
 impl Default for Client {
     fn default() -> Client {
-        Client {
-            token: String::new(),
-            inner: ::reqwest::Client::new(),
-        }
+        Client::builder(String::new()).finish().expect("the default base URL is valid")
     }
 }
 
@@ -62,80 +106,115 @@ impl Client {
     pub fn new<T>(token: T) -> Client
         where T: Into<String>
     {
-        Client {
+        Client::builder(token).finish().expect("the default base URL is valid")
+    }
+
+    /// Creates a [`ClientBuilder`](struct.ClientBuilder.html)
+    /// to configure a `Client`.
+    pub fn builder<T>(token: T) -> ClientBuilder
+        where T: Into<String>
+    {
+        ClientBuilder {
             token: token.into(),
-            ..Default::default()
+            base_url: BASE_URL.to_owned(),
+            api_version: API_VERSION.to_owned(),
+            transport: None,
+            retry: RetryPolicy::never(),
+            limiter: None,
         }
     }
 
+    /// Constructs a new `Client` which talks to the server at `base_url`.
+    pub fn with_base_url<T, U>(token: T, base_url: U) -> Result<Client, Error>
+        where T: Into<String>, U: Into<String>
+    {
+        let mut builder = Client::builder(token);
+        builder.base_url(base_url);
+        builder.finish()
+    }
+
+    /// The root URL every endpoint is resolved against,
+    /// e.g. `https://api.pomotodo.com/1/`.
+    pub fn root_url(&self) -> &Url {
+        &self.api.root
+    }
+
     /// Request for the `Account`'s profile.
     pub fn account(&self) -> Result<Account, Error> {
-        self.get(INFO_URL)
+        self.get(self.url("account")?)
     }
 
     /// Request for the `Pomo` specified by `uuid`.
     pub fn pomo<U: Into<Uuid>>(&self, uuid: U) -> Result<Pomo, Error> {
-        let url = format!("{}/{}", POMO_URL, uuid.into());
-        self.get(url.as_str())
+        let url = self.url(&format!("pomos/{}", uuid.into()))?;
+        self.get(url)
     }
 
     /// Request for all `Pomo` that matched the `param`.
     pub fn pomos(&self, param: PomoParameter) -> Result<Vec<Pomo>, Error> {
-        let query = param.to_query();
-        let url = if !query.is_empty() {
-            POMO_URL.to_owned()
-        } else {
-            format!("{}?{}", POMO_URL, query)
-        };
+        let url = self.api.query_url("pomos", &param.query())?;
+        self.get(url)
+    }
 
-        self.get(url.as_str())
+    /// Iterate over every `Pomo` that matched the `param`, requesting
+    /// the pages lazily, see [`PomoIter`](struct.PomoIter.html).
+    pub fn pomos_iter(&self, param: PomoParameter) -> PomoIter {
+        PomoIter::new(self, param)
     }
 
     /// Submit a new `Pomo` to server.
+    ///
+    /// Fails with `ErrorKind::Invalid` without any request
+    /// if the `pomo` is rejected by [`Validate`](trait.Validate.html).
     pub fn submit_pomo(&self, pomo: &Pomo) -> Result<Pomo, Error> {
-        self.post(POMO_URL, pomo)
+        ensure_valid(pomo)?;
+        self.post(self.url("pomos")?, pomo)
     }
 
-    /// Request server to update an existed `Pomo`,
-    /// only allow to update the `description`.
-    pub fn update_pomo<U, S>(&self, uuid: U, desc: S) -> Result<Pomo, Error>
-        where U: Into<Uuid>, S: Into<String>
-    {
-        let url = format!("{}/{}", POMO_URL, uuid.into());
-        let json = json!({
-            "description": desc.into(),
-        });
-
-        self.patch(url.as_str(), &json)
+    /// Request server to update an existed `Pomo`.
+    ///
+    /// The fields allowed to be updated:
+    ///
+    /// * `description`
+    ///
+    /// Only the fields touched by the `patch` are sent.
+    pub fn update_pomo<U: Into<Uuid>>(&self, uuid: U, patch: &PomoPatch) -> Result<Pomo, Error> {
+        ensure_valid(patch)?;
+        let url = self.url(&format!("pomos/{}", uuid.into()))?;
+        self.patch(url, patch)
     }
 
     /// Requests server to delete the `Pomo` specified by `uuid`.
     pub fn delete_pomo<U: Into<Uuid>>(&self, uuid: U) -> Result<(), Error> {
-        let url = format!("{}/{}", POMO_URL, uuid.into());
-        self.delete(url.as_str())
+        let url = self.url(&format!("pomos/{}", uuid.into()))?;
+        self.delete(url)
     }
 
     /// Request for the `Todo` specified by `uuid`.
     pub fn todo<U: Into<Uuid>>(&self, uuid: U) -> Result<Todo, Error> {
-        let url = format!("{}/{}", TODO_URL, uuid.into());
-        self.get(url.as_str())
+        let url = self.url(&format!("todos/{}", uuid.into()))?;
+        self.get(url)
     }
 
     /// Request for all `Todo` that match with the `param`.
     pub fn todos(&self, param: TodoParameter) -> Result<Vec<Todo>, Error> {
-        let query = param.to_query();
-        let url = if !query.is_empty() {
-            TODO_URL.to_owned()
-        } else {
-            format!("{}?{}", TODO_URL, query)
-        };
+        let url = self.api.query_url("todos", &param.query())?;
+        self.get(url)
+    }
 
-        self.get(url.as_str())
+    /// Iterate over every `Todo` that matched the `param`, requesting
+    /// the pages lazily, see [`TodoIter`](struct.TodoIter.html).
+    pub fn todos_iter(&self, param: TodoParameter) -> TodoIter {
+        TodoIter::new(self, param)
     }
 
     /// Requests server to creates a new `Todo`.
+    ///
+    /// Fails with `ErrorKind::Invalid` without any request
+    /// if the `todo` is rejected by [`Validate`](trait.Validate.html).
     pub fn create_todo(&self, todo: &Todo) -> Result<Todo, Error> {
-        self.post(TODO_URL, todo)
+        ensure_valid(todo)?;
+        self.post(self.url("todos")?, todo)
     }
 
     /// Request server to update an existed `Todo`.
@@ -151,29 +230,30 @@ impl Client {
     /// * `remind_time`
     /// * `estimated_pomo_count`
     /// * `costed_pomo_count`
-    pub fn update_todo<U: Into<Uuid>>(&self, uuid: U, todo: &Todo) -> Result<Todo, Error> {
-        let url = format!("{}/{}", TODO_URL, uuid.into());
-
-        // TODO: Validate the item
-        self.patch(url.as_str(), todo)
+    ///
+    /// Only the fields touched by the `patch` are sent.
+    pub fn update_todo<U: Into<Uuid>>(&self, uuid: U, patch: &TodoPatch) -> Result<Todo, Error> {
+        ensure_valid(patch)?;
+        let url = self.url(&format!("todos/{}", uuid.into()))?;
+        self.patch(url, patch)
     }
 
     /// Requests server to delete the `Todo` specified by `uuid`.
     pub fn delete_todo<U: Into<Uuid>>(&self, uuid: U) -> Result<(), Error> {
-        let url = format!("{}/{}", TODO_URL, uuid.into());
-        self.delete(url.as_str())
+        let url = self.url(&format!("todos/{}", uuid.into()))?;
+        self.delete(url)
     }
 
     /// Request for the `SubTodo` owned by `parent` and has the `uuid`.
     pub fn subtodo<U: Into<Uuid>>(&self, parent: U, uuid: U) -> Result<SubTodo, Error> {
-        let url = format!("{}/{}/sub_todos/{}", TODO_URL, parent.into(), uuid.into());
-        self.get(url.as_str())
+        let url = self.url(&format!("todos/{}/sub_todos/{}", parent.into(), uuid.into()))?;
+        self.get(url)
     }
 
     /// Request for all `SubTodo` owned by `parent`.
     pub fn subtodos<U: Into<Uuid>>(&self, parent: U) -> Result<Vec<SubTodo>, Error> {
-        let url = format!("{}/{}/sub_todos", TODO_URL, parent.into());
-        self.get(url.as_str())
+        let url = self.url(&format!("todos/{}/sub_todos", parent.into()))?;
+        self.get(url)
     }
 
     /// Requests server to create a new `SubTodo` under the
@@ -183,8 +263,9 @@ impl Client {
         parent: U,
         sub_todo: &SubTodo,
     ) -> Result<SubTodo, Error> {
-        let url = format!("{}/{}/sub_todos", TODO_URL, parent.into());
-        self.post(url.as_str(), sub_todo)
+        ensure_valid(sub_todo)?;
+        let url = self.url(&format!("todos/{}/sub_todos", parent.into()))?;
+        self.post(url, sub_todo)
     }
 
     /// Request server to update an existed `SubTodo`.
@@ -195,72 +276,289 @@ impl Client {
     /// * `parent_uuid` (allowed, but will be dropped)
     /// * `completed`
     /// * `completed_at`
+    ///
+    /// Only the fields touched by the `patch` are sent.
     pub fn update_subtodo<U: Into<Uuid>>(
         &self,
         parent: U,
         uuid: U,
-        sub_todo: &SubTodo,
+        patch: &SubTodoPatch,
     ) -> Result<SubTodo, Error> {
-        let url = format!("{}/{}/sub_todos/{}", TODO_URL, parent.into(), uuid.into());
-
-        // TODO: Validate the item
-        self.patch(url.as_str(), sub_todo)
+        ensure_valid(patch)?;
+        let url = self.url(&format!("todos/{}/sub_todos/{}", parent.into(), uuid.into()))?;
+        self.patch(url, patch)
     }
 
     /// Requests server to delete the [`SubTodo`](struct.Todo.html)
     /// owned by `parent`and had the `uuid`.
     pub fn delete_subtodo<U: Into<Uuid>>(&self, parent: U, uuid: U) -> Result<(), Error> {
-        let url = format!("{}/{}/sub_todos/{}", TODO_URL, parent.into(), uuid.into());
-        self.delete(url.as_str())
+        let url = self.url(&format!("todos/{}/sub_todos/{}", parent.into(), uuid.into()))?;
+        self.delete(url)
+    }
+
+    /// Resolves the endpoint `path` against the root URL.
+    fn url(&self, path: &str) -> Result<Url, Error> {
+        self.api.url(path)
     }
 
-    /// An wrap of `reqwest::Client::request` to make request with json body.
-    fn request<U, I>(&self, method: Method, url: U, json: Option<&I>) -> Result<Response, Error>
-        where U: IntoUrl, I: Serialize
+    /// Makes a request with an optional json body through the `Transport`.
+    fn request<I>(&self, method: Method, url: Url, json: Option<&I>) -> Result<HttpResponse, Error>
+        where I: Serialize
     {
-        let mut request = self.inner.request(method, url);
-        if let Some(json) = json {
-            request.json(json);
+        let request = self.api.request(method, url.clone(), json)?;
+
+        let mut attempt = 1;
+        loop {
+            if let Some(ref limiter) = self.limiter {
+                limiter.acquire();
+            }
+
+            let error = match self.transport
+                      .send(request.clone())
+                      .and_then(|resp| {
+                                    if let Some(ref limiter) = self.limiter {
+                                        limiter.observe(&resp);
+                                    }
+                                    check(&url, resp)
+                                }) {
+                Ok(resp) => return Ok(resp),
+                Err(error) => error,
+            };
+
+            if !self.retry.permits(&request.method, attempt, &error) {
+                return Err(error);
+            }
+
+            thread::sleep(self.retry.delay(attempt, &error));
+            attempt += 1;
         }
-
-        request
-            .header(Authorization(format!("token {}", self.token)))
-            .send()
-            .and_then(|resp| resp.error_for_status())
-            .map_err(|e| e.into())
-            .and_then(|resp| if resp.status() == StatusCode::Ok {
-                          Ok(resp)
-                      } else {
-                          Err(Error::from(ErrorKind::Msg("server redirect".to_owned())))
-                      })
     }
 
     /// Convenience method to make a GET request body to a URL.
-    fn get<U, O>(&self, url: U) -> Result<O, Error>
-        where U: IntoUrl, O: DeserializeOwned
-    {
-        self.request::<_, ()>(Method::Get, url, None)
-            .and_then(|mut resp| resp.json().map_err(|e| e.into()))
+    fn get<O: DeserializeOwned>(&self, url: Url) -> Result<O, Error> {
+        self.request::<()>(Method::Get, url, None).and_then(decode)
     }
 
     /// Convenience method to make a POST request with json body to a URL.
-    fn post<U, I, O>(&self, url: U, json: &I) -> Result<O, Error>
-        where U: IntoUrl, I: Serialize, O: DeserializeOwned
+    fn post<I, O>(&self, url: Url, json: &I) -> Result<O, Error>
+        where I: Serialize, O: DeserializeOwned
     {
-        self.request(Method::Post, url, Some(json))
-            .and_then(|mut resp| resp.json().map_err(|e| e.into()))
+        self.request(Method::Post, url, Some(json)).and_then(decode)
     }
 
     /// Convenience method to make a PATCH request with json body to a URL.
-    fn patch<U, I, O>(&self, url: U, json: &I) -> Result<O, Error>
-        where U: IntoUrl, I: Serialize, O: DeserializeOwned
+    fn patch<I, O>(&self, url: Url, json: &I) -> Result<O, Error>
+        where I: Serialize, O: DeserializeOwned
     {
-        self.request(Method::Patch, url, Some(json))
-            .and_then(|mut resp| resp.json().map_err(|e| e.into()))
+        self.request(Method::Patch, url, Some(json)).and_then(decode)
     }
 
     /// Convenience method to make a DELETE request to a URL.
-    fn delete<U: IntoUrl>(&self, url: U) -> Result<(), Error> {
-        self.request::<_, ()>(Method::Delete, url, None).and_then(|_| Ok(()))
+    fn delete(&self, url: Url) -> Result<(), Error> {
+        self.request::<()>(Method::Delete, url, None).and_then(|_| Ok(()))
+    }
+}
+
+impl ClientBuilder {
+    /// Set the access token.
+    pub fn token<T: Into<String>>(&mut self, token: T) -> &mut ClientBuilder {
+        self.token = token.into();
+        self
     }
+
+    /// Set the base URL of the server, defaults to `https://api.pomotodo.com`.
+    pub fn base_url<U: Into<String>>(&mut self, base_url: U) -> &mut ClientBuilder {
+        self.base_url = base_url.into();
+        self
+    }
+
+    /// Set the API version, defaults to `1`.
+    pub fn api_version<V: Into<String>>(&mut self, version: V) -> &mut ClientBuilder {
+        self.api_version = version.into();
+        self
+    }
+
+    /// Set the [`Transport`](trait.Transport.html) sending the requests,
+    /// defaults to a [`ReqwestTransport`](struct.ReqwestTransport.html).
+    pub fn transport<T: Transport + 'static>(&mut self, transport: T) -> &mut ClientBuilder {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Set the [`RetryPolicy`](struct.RetryPolicy.html) for failed requests,
+    /// defaults to [`RetryPolicy::never`](struct.RetryPolicy.html#method.never).
+    pub fn retry(&mut self, policy: RetryPolicy) -> &mut ClientBuilder {
+        self.retry = policy;
+        self
+    }
+
+    /// Attach a [`RateLimiter`](struct.RateLimiter.html) pacing the requests,
+    /// there is none by default.
+    pub fn rate_limiter(&mut self, limiter: RateLimiter) -> &mut ClientBuilder {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// Build a [`Client`](struct.Client.html).
+    ///
+    /// Fails if the base URL can not be parsed.
+    pub fn finish(self) -> Result<Client, Error> {
+        let api = self.api()?;
+        let transport: Arc<Transport> = match self.transport {
+            Some(transport) => transport,
+            None => Arc::new(ReqwestTransport::new()),
+        };
+
+        Ok(Client {
+               api: api,
+               transport: transport,
+               retry: self.retry,
+               limiter: self.limiter,
+           })
+    }
+
+    /// Resolves the access token and root URL.
+    pub(crate) fn api(&self) -> Result<Api, Error> {
+        let base = format!("{}/{}/",
+                           self.base_url.trim_right_matches('/'),
+                           self.api_version.trim_matches('/'));
+
+        Ok(Api {
+               token: self.token.clone(),
+               root: Url::parse(&base)?,
+           })
+    }
+}
+
+impl Api {
+    /// Resolves the endpoint `path` against the root URL.
+    pub(crate) fn url(&self, path: &str) -> Result<Url, Error> {
+        self.root.join(path).map_err(|e| e.into())
+    }
+
+    /// Resolves the endpoint `path` with the encoded `query` attached.
+    pub(crate) fn query_url(&self, path: &str, query: &Query) -> Result<Url, Error> {
+        let mut url = self.url(path)?;
+        query.append_to(&mut url);
+        Ok(url)
+    }
+
+    /// Builds a request carrying the access token and an optional json body.
+    pub(crate) fn request<I>(&self,
+                             method: Method,
+                             url: Url,
+                             json: Option<&I>)
+                             -> Result<HttpRequest, Error>
+        where I: Serialize
+    {
+        let mut headers = vec![("Authorization".to_owned(), format!("token {}", self.token))];
+        let body = match json {
+            Some(json) => {
+                headers.push(("Content-Type".to_owned(), "application/json".to_owned()));
+                Some(serde_json::to_vec(json)?)
+            }
+            None => None,
+        };
+
+        Ok(HttpRequest {
+               method: method,
+               url: url,
+               headers: headers,
+               body: body,
+           })
+    }
+}
+
+/// The error body returned by Pomotodo server, every field is optional.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ErrorBody {
+    message: Option<String>,
+    error: Option<String>,
+    field: Option<String>,
+    errors: Vec<FieldError>,
+}
+
+/// A single invalid field reported in an [`ErrorBody`](struct.ErrorBody.html).
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct FieldError {
+    field: String,
+    message: String,
+}
+
+/// Turns a response to the request for `url` which is not successful
+/// into an `Error` of the matching kind.
+pub(crate) fn check(url: &Url, resp: HttpResponse) -> Result<HttpResponse, Error> {
+    let status = resp.status;
+    if status >= 200 && status < 300 {
+        return Ok(resp);
+    }
+
+    let body: ErrorBody = serde_json::from_slice(&resp.body).unwrap_or_default();
+    let message = body.message
+        .or(body.error)
+        .unwrap_or_else(|| String::from_utf8_lossy(&resp.body).into_owned());
+
+    let kind = match status {
+        401 => ErrorKind::Unauthorized,
+        404 => {
+            let uuid = url.path_segments()
+                .and_then(|segments| segments.filter_map(|s| Uuid::parse_str(s).ok()).last());
+            match uuid {
+                Some(uuid) => ErrorKind::NotFound(uuid),
+                None => ErrorKind::Http(status, message),
+            }
+        }
+        422 => {
+            match body.errors.into_iter().next() {
+                Some(error) => {
+                    ErrorKind::Validation {
+                        field: error.field,
+                        message: error.message,
+                    }
+                }
+                None => {
+                    ErrorKind::Validation {
+                        field: body.field.unwrap_or_default(),
+                        message: message,
+                    }
+                }
+            }
+        }
+        429 => ErrorKind::RateLimited { retry_after: retry_after(&resp) },
+        status if status >= 500 => ErrorKind::Server(status),
+        _ => ErrorKind::Http(status, message),
+    };
+
+    Err(kind.into())
+}
+
+/// Whether the `error` is a `NotFound` returned by `check`.
+pub(crate) fn is_not_found(error: &Error) -> bool {
+    match *error.kind() {
+        ErrorKind::NotFound(_) => true,
+        _ => false,
+    }
+}
+
+/// Reads the `Retry-After` header, either in seconds or as an HTTP date.
+pub(crate) fn retry_after(resp: &HttpResponse) -> Option<Duration> {
+    let value = match resp.header("Retry-After") {
+        Some(value) => value.trim(),
+        None => return None,
+    };
+
+    if let Ok(secs) = value.parse() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    DateTime::parse_from_rfc2822(value)
+        .ok()
+        .and_then(|at| at.signed_duration_since(Utc::now()).to_std().ok())
+}
+
+/// Decodes the json body of a response.
+pub(crate) fn decode<O: DeserializeOwned>(resp: HttpResponse) -> Result<O, Error> {
+    serde_json::from_slice(&resp.body).map_err(|e| e.into())
 }
@@ -0,0 +1,38 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use errors::*;
+
+/// Writes a file through `write` into a temporary file next to `path`,
+/// then moves it in place, so a crash leaves either the old or the new
+/// content.
+pub(crate) fn write_atomically<F>(path: &Path, write: F) -> Result<(), Error>
+    where F: FnOnce(&mut File) -> Result<(), Error>
+{
+    let mut temp = path.to_path_buf().into_os_string();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+
+    {
+        let mut file = File::create(&temp)?;
+        write(&mut file)?;
+        file.flush()?;
+        file.sync_all()?;
+    }
+    fs::rename(&temp, path)?;
+    Ok(())
+}
@@ -0,0 +1,1010 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! iCalendar (RFC 5545) export and import of `Pomo`s and `Todo`s.
+//!
+//! A [`Calendar`](struct.Calendar.html) renders:
+//!
+//! * every `Pomo` as a `VEVENT` from `started_at` to `ended_at`, with the
+//!   `description` as `SUMMARY` and `uuid` as `UID`. An abandoned one is
+//!   `CANCELLED` and has the `Abandoned` category.
+//! * every `Todo` as a `VTODO`, starting and due at its `remind_time` with
+//!   an alarm, and repeating by an `RRULE` from its `repeat_type`.
+//! * every `SubTodo` as a `VTODO` related to its parent.
+//!
+//! The times are written in UTC, except the start and due of the `Todo`s in
+//! the calendar's [timezone](struct.Calendar.html#method.with_timezone),
+//! which should be the one of the account. Their recurrences are then
+//! expanded in local time as Pomotodo does, rather than drift by an hour
+//! across a daylight saving change. That timezone is described by a
+//! `VTIMEZONE` listing its offset changes up to ten years past the last
+//! `remind_time`. A monthly `Todo` past the 28th and a
+//! yearly one on 29 February fall on the last day of the shorter months.
+//!
+//! The other way round, an [`ImportPlan`](struct.ImportPlan.html) reads the
+//! `VTODO`s of a calendar as `Todo`s, the ones `RELATED-TO` a parent as its
+//! `SubTodo`s, and the past `VEVENT`s as manual `Pomo`s. What can not be
+//! carried over, e.g. an `RRULE` with no matching `RepeatType`, is listed in
+//! its `warnings`, so the plan can be reviewed before it is applied.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! # extern crate chrono;
+//! # extern crate pomotodo;
+//! # use std::fs::File;
+//! # use std::io::Read;
+//! # use chrono::Utc;
+//! # use pomotodo::{Client, PomoParameter, TodoParameter};
+//! # use pomotodo::ical::{Calendar, ImportPlan};
+//! #
+//! # fn run() -> Result<(), pomotodo::errors::Error> {
+//! let client = Client::new("YOUR_ACCESS_TOKEN");
+//! let pomos = client.pomos(PomoParameter::default())?;
+//! let todos = client.todos(TodoParameter::default())?;
+//!
+//! let tz = client.account()?.tz()?;
+//!
+//! let mut calendar = Calendar::new();
+//! calendar
+//!     .with_name("Pomotodo")
+//!     .with_timezone(tz)
+//!     .add_pomos(&pomos)
+//!     .add_todos(&todos);
+//! calendar.write_to(File::create("pomotodo.ics")?)?;
+//!
+//! let mut ics = String::new();
+//! File::open("tasks.ics")?.read_to_string(&mut ics)?;
+//! let plan = ImportPlan::parse(&ics, tz, Utc::now())?;
+//! for warning in &plan.warnings {
+//!     println!("{}", warning);
+//! }
+//! let report = plan.apply(&client);
+//! println!("created {} todos and {} pomos", report.todos.len(), report.pomos.len());
+//! if let Some(e) = report.error {
+//!     return Err(e);
+//! }
+//! # Ok(())
+//! # }
+//! # fn main() {}
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::{self, Write as FmtWrite};
+use std::io::Write;
+
+use uuid::Uuid;
+use chrono::prelude::*;
+use chrono::Duration;
+
+use chrono_tz;
+
+use errors::*;
+use client::Client;
+use pomo::Pomo;
+use todo::{Todo, SubTodo, RepeatType};
+use repeat::resolve;
+use validate::Validate;
+
+/// The longest content line in octets, longer ones are folded.
+const LINE_LENGTH: usize = 75;
+
+const PROD_ID: &'static str = "-//Pomotodo//Pomotodo SDK for Rust//EN";
+
+/// How many years past the last `remind_time` a `VTIMEZONE` covers.
+const TIMEZONE_YEARS: i32 = 10;
+
+/// An iCalendar of `Pomo`s and `Todo`s, written by its `Display`,
+/// see the [module documentation](index.html).
+#[derive(Debug, Clone, Default)]
+pub struct Calendar<'a> {
+    name: Option<String>,
+    timezone: Option<chrono_tz::Tz>,
+    pomos: Vec<&'a Pomo>,
+    todos: Vec<&'a Todo>,
+    sub_todos: Vec<&'a SubTodo>,
+}
+
+/// The records to create from an iCalendar, see the
+/// [module documentation](index.html).
+#[derive(Debug, Clone, Default)]
+pub struct ImportPlan {
+    /// The `Todo`s with their `SubTodo`s, in the order of the calendar.
+    pub todos: Vec<PlannedTodo>,
+
+    /// The manual `Pomo`s from the past events.
+    pub pomos: Vec<Pomo>,
+
+    /// What was skipped or changed while reading the calendar.
+    pub warnings: Vec<String>,
+}
+
+/// A `Todo` of an [`ImportPlan`](struct.ImportPlan.html).
+#[derive(Debug, Clone)]
+pub struct PlannedTodo {
+    /// The `UID` of the `VTODO`.
+    pub uid: String,
+
+    pub todo: Todo,
+
+    pub sub_todos: Vec<SubTodo>,
+}
+
+/// The records created by [`ImportPlan::apply`](struct.ImportPlan.html#method.apply).
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    /// The `Todo`s created, in the order of the plan, including one whose
+    /// `SubTodo`s failed.
+    pub todos: Vec<Todo>,
+
+    pub sub_todos: Vec<SubTodo>,
+
+    pub pomos: Vec<Pomo>,
+
+    /// The failure which stopped the import, if any.
+    pub error: Option<Error>,
+}
+
+/// A component of a parsed calendar, e.g. a `VTODO`.
+#[derive(Debug, Clone, Default)]
+struct Component {
+    name: String,
+    properties: Vec<Property>,
+    components: Vec<Component>,
+}
+
+/// A content line of a parsed calendar, the names in upper case.
+#[derive(Debug, Clone)]
+struct Property {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+/// Turns the components of a parsed calendar into records in the timezone
+/// `Tz`, which is used for the times without a timezone.
+struct Importer<Tz: TimeZone> {
+    tz: Tz,
+    warnings: Vec<String>,
+}
+
+impl<'a> Calendar<'a> {
+    /// Constructs an empty `Calendar`.
+    pub fn new() -> Calendar<'a> {
+        Calendar::default()
+    }
+
+    /// Set the name shown by the calendar apps.
+    pub fn with_name<S: Into<String>>(&mut self, name: S) -> &mut Calendar<'a> {
+        self.name = Some(name.into());
+        self
+    }
+
+    /// Set the timezone the `Todo`s repeat in, the timezone of the account.
+    /// Without it, the times are all in UTC.
+    pub fn with_timezone(&mut self, tz: chrono_tz::Tz) -> &mut Calendar<'a> {
+        self.timezone = Some(tz);
+        self
+    }
+
+    /// Add the `pomos` as events.
+    pub fn add_pomos(&mut self, pomos: &'a [Pomo]) -> &mut Calendar<'a> {
+        self.pomos.extend(pomos);
+        self
+    }
+
+    /// Add the `todos` as to-dos.
+    pub fn add_todos(&mut self, todos: &'a [Todo]) -> &mut Calendar<'a> {
+        self.todos.extend(todos);
+        self
+    }
+
+    /// Add the `sub_todos` as to-dos related to their `parent_uuid`.
+    pub fn add_sub_todos(&mut self, sub_todos: &'a [SubTodo]) -> &mut Calendar<'a> {
+        self.sub_todos.extend(sub_todos);
+        self
+    }
+
+    /// Writes the calendar to `out`.
+    pub fn write_to<W: Write>(&self, mut out: W) -> Result<(), Error> {
+        out.write_all(self.to_string().as_bytes())?;
+        out.flush()?;
+        Ok(())
+    }
+
+    /// The unfolded content lines of the calendar.
+    fn lines(&self) -> Vec<String> {
+        let mut lines = vec!["BEGIN:VCALENDAR".to_owned(),
+                             "VERSION:2.0".to_owned(),
+                             format!("PRODID:{}", PROD_ID),
+                             "CALSCALE:GREGORIAN".to_owned()];
+        if let Some(ref name) = self.name {
+            lines.push(format!("X-WR-CALNAME:{}", escape(name)));
+        }
+
+        if let Some(tz) = self.timezone {
+            let years = self.todos
+                .iter()
+                .filter_map(|todo| todo.remind_time)
+                .map(|time| time.with_timezone(&tz).year())
+                .collect::<Vec<_>>();
+            if let (Some(&first), Some(&last)) = (years.iter().min(), years.iter().max()) {
+                vtimezone(tz, first, last + TIMEZONE_YEARS, &mut lines);
+            }
+        }
+
+        for pomo in &self.pomos {
+            event(pomo, &mut lines);
+        }
+        for todo in &self.todos {
+            vtodo(todo, self.timezone, &mut lines);
+        }
+        for sub_todo in &self.sub_todos {
+            sub_vtodo(sub_todo, &mut lines);
+        }
+
+        lines.push("END:VCALENDAR".to_owned());
+        lines
+    }
+}
+
+impl<'a> fmt::Display for Calendar<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for line in self.lines() {
+            fold(&line, f)?;
+        }
+        Ok(())
+    }
+}
+
+impl ImportPlan {
+    /// Reads the iCalendar `text`, taking the times without a timezone in
+    /// `tz`, which should be the timezone of the account. Only the events
+    /// ended by `now` are planned as `Pomo`s.
+    ///
+    /// Fails if the `text` is not an iCalendar at all, the components which
+    /// can not be imported are only skipped with a warning.
+    pub fn parse<Tz: TimeZone>(text: &str,
+                               tz: Tz,
+                               now: DateTime<Utc>)
+                               -> Result<ImportPlan, Error> {
+        let calendar = parse(text)?;
+        let mut importer = Importer {
+            tz: tz,
+            warnings: Vec::new(),
+        };
+
+        let mut todos = Vec::new();
+        let mut children = Vec::new();
+        for vtodo in calendar.components("VTODO") {
+            let uid = vtodo.text("UID").unwrap_or_else(|| Uuid::new_v4().to_string());
+            match vtodo.parent() {
+                Some(parent) => children.push((uid, parent, vtodo)),
+                None => {
+                    if let Some(todo) = importer.todo(vtodo) {
+                        todos.push(PlannedTodo {
+                                       uid: uid,
+                                       todo: todo,
+                                       sub_todos: Vec::new(),
+                                   });
+                    }
+                }
+            }
+        }
+
+        // Pomotodo has a single level of `SubTodo`s, so the deeper ones go
+        // to their topmost ancestor.
+        let parents = children
+            .iter()
+            .map(|&(ref uid, ref parent, _)| (uid.as_str(), parent.as_str()))
+            .collect::<HashMap<_, _>>();
+        for &(_, ref parent, vtodo) in &children {
+            let mut root = parent.as_str();
+            for _ in 0..children.len() {
+                match parents.get(root) {
+                    Some(&next) => root = next,
+                    None => break,
+                }
+            }
+
+            match todos.iter_mut().find(|planned| planned.uid == root) {
+                Some(planned) => {
+                    if let Some(sub_todo) = importer.sub_todo(vtodo) {
+                        planned.sub_todos.push(sub_todo);
+                    }
+                }
+                None => importer.warn(vtodo, format!("skipped, parent `{}` is not found", parent)),
+            }
+        }
+
+        let mut pomos = Vec::new();
+        for vevent in calendar.components("VEVENT") {
+            if let Some(pomo) = importer.pomo(vevent) {
+                if pomo.ended_at <= now {
+                    pomos.push(pomo);
+                }
+            }
+        }
+
+        Ok(ImportPlan {
+               todos: todos,
+               pomos: pomos,
+               warnings: importer.warnings,
+           })
+    }
+
+    /// Creates the planned records on server, each `Todo` followed by its
+    /// `SubTodo`s, then the `Pomo`s.
+    ///
+    /// Stops at the first failure, which is kept in the `error` of the
+    /// report together with the records created so far, so that they can
+    /// be left out of another try.
+    pub fn apply(&self, client: &Client) -> ImportReport {
+        let mut report = ImportReport::default();
+        if let Err(e) = self.create(client, &mut report) {
+            report.error = Some(e);
+        }
+        report
+    }
+
+    /// Creates the planned records, adding each one to the `report`.
+    fn create(&self, client: &Client, report: &mut ImportReport) -> Result<(), Error> {
+        for planned in &self.todos {
+            let todo = client
+                .create_todo(&planned.todo)
+                .chain_err(|| format!("failed to import `{}`", planned.todo.description))?;
+            let parent = todo.uuid;
+            report.todos.push(todo);
+            let parent = parent.ok_or_else(|| Error::from("the todo has no uuid"))?;
+
+            for sub_todo in &planned.sub_todos {
+                let sub_todo = client
+                    .create_subtodo(parent, sub_todo)
+                    .chain_err(|| format!("failed to import `{}`", sub_todo.description))?;
+                report.sub_todos.push(sub_todo);
+            }
+        }
+
+        for pomo in &self.pomos {
+            let pomo = client
+                .submit_pomo(pomo)
+                .chain_err(|| format!("failed to import `{}`", pomo.description))?;
+            report.pomos.push(pomo);
+        }
+
+        Ok(())
+    }
+}
+
+impl<Tz: TimeZone> Importer<Tz> {
+    fn todo(&mut self, vtodo: &Component) -> Option<Todo> {
+        let start = vtodo.property("DTSTART").and_then(|p| self.time(vtodo, p));
+        let due = vtodo.property("DUE").and_then(|p| self.time(vtodo, p));
+        let alarm = vtodo
+            .components("VALARM")
+            .into_iter()
+            .filter_map(|alarm| self.alarm(vtodo, alarm, start, due))
+            .min();
+        let remind_time = alarm.or(due);
+
+        let repeat_type = match vtodo.property("RRULE") {
+            Some(_) if remind_time.is_none() => {
+                self.warn(vtodo, "not repeating, it has no due time nor alarm".to_owned());
+                None
+            }
+            Some(rule) => self.repeat_type(vtodo, &rule.value),
+            None => None,
+        };
+        let priority = vtodo.text("PRIORITY").and_then(|p| p.trim().parse::<u8>().ok());
+
+        let todo = Todo {
+            description: vtodo.text("SUMMARY").unwrap_or_default(),
+            notice: vtodo.text("DESCRIPTION"),
+            pin: match priority {
+                Some(1...4) => Some(true),
+                _ => None,
+            },
+            completed: Some(vtodo.is_completed()),
+            completed_at: vtodo.property("COMPLETED").and_then(|p| self.time(vtodo, p)),
+            repeat_type: repeat_type,
+            remind_time: remind_time,
+            ..Default::default()
+        };
+        self.check(vtodo, todo)
+    }
+
+    fn sub_todo(&mut self, vtodo: &Component) -> Option<SubTodo> {
+        let sub_todo = SubTodo {
+            description: vtodo.text("SUMMARY").unwrap_or_default(),
+            completed: Some(vtodo.is_completed()),
+            completed_at: vtodo.property("COMPLETED").and_then(|p| self.time(vtodo, p)),
+            ..Default::default()
+        };
+        self.check(vtodo, sub_todo)
+    }
+
+    fn pomo(&mut self, vevent: &Component) -> Option<Pomo> {
+        let start = vevent.property("DTSTART")?;
+        if start.is_date() {
+            return None;
+        }
+        let started_at = self.time(vevent, start)?;
+        let ended_at = match (vevent.property("DTEND"), vevent.text("DURATION")) {
+            (Some(end), _) => self.time(vevent, end)?,
+            (None, Some(length)) => {
+                let ended_at = duration(&length)
+                    .and_then(|length| started_at.checked_add_signed(length));
+                match ended_at {
+                    Some(ended_at) => ended_at,
+                    None => {
+                        self.warn(vevent, format!("skipped, invalid DURATION `{}`", length));
+                        return None;
+                    }
+                }
+            }
+            (None, None) => started_at,
+        };
+        if vevent.property("RRULE").is_some() {
+            self.warn(vevent, "only the first occurrence is imported".to_owned());
+        }
+
+        let pomo = Pomo {
+            description: vevent.text("SUMMARY").unwrap_or_default(),
+            started_at: started_at,
+            ended_at: ended_at,
+            local_started_at: Some(started_at.with_timezone(&self.tz).naive_local()),
+            local_ended_at: Some(ended_at.with_timezone(&self.tz).naive_local()),
+            abandoned: Some(vevent.text("STATUS").map_or(false, |s| s == "CANCELLED")),
+            manual: Some(true),
+            ..Default::default()
+        };
+        self.check(vevent, pomo)
+    }
+
+    /// When the `alarm` of a `VTODO` starting at `start` and due at `due` goes off.
+    fn alarm(&mut self,
+             vtodo: &Component,
+             alarm: &Component,
+             start: Option<DateTime<Utc>>,
+             due: Option<DateTime<Utc>>)
+             -> Option<DateTime<Utc>> {
+        let trigger = alarm.property("TRIGGER")?;
+        if trigger.param("VALUE") == Some("DATE-TIME") {
+            return self.time(vtodo, trigger);
+        }
+
+        let offset = match duration(&trigger.value) {
+            Some(offset) => offset,
+            None => {
+                self.warn(vtodo, format!("ignored the alarm, invalid TRIGGER `{}`", trigger.value));
+                return None;
+            }
+        };
+        let base = match trigger.param("RELATED") {
+            Some("END") => due,
+            _ => start.or(due),
+        }?;
+
+        let at = base.checked_add_signed(offset);
+        if at.is_none() {
+            let message = format!("ignored the alarm, TRIGGER `{}` out of range", trigger.value);
+            self.warn(vtodo, message);
+        }
+        at
+    }
+
+    /// The `RepeatType` of the `rule`, if any matches.
+    fn repeat_type(&mut self, vtodo: &Component, rule: &str) -> Option<RepeatType> {
+        let mut freq = String::new();
+        let mut interval = 1;
+        let mut ignored = Vec::new();
+        for part in rule.split(';') {
+            let mut pair = part.splitn(2, '=');
+            let name = pair.next().unwrap_or("").to_uppercase();
+            let value = pair.next().unwrap_or("");
+            match name.as_str() {
+                "FREQ" => freq = value.to_uppercase(),
+                "INTERVAL" => interval = value.parse().unwrap_or(0),
+                // The day of the `remind_time` already tells these.
+                "WKST" | "BYMONTH" | "BYMONTHDAY" | "BYSETPOS" => {}
+                _ => ignored.push(part),
+            }
+        }
+
+        let repeat = match (freq.as_str(), interval) {
+            ("DAILY", 1) => RepeatType::EachDay,
+            ("WEEKLY", 1) => RepeatType::EachWeek,
+            ("WEEKLY", 2) => RepeatType::EachTwoWeek,
+            ("MONTHLY", 1) => RepeatType::EachMonth,
+            ("YEARLY", 1) => RepeatType::EachYear,
+            _ => {
+                self.warn(vtodo, format!("not repeating, unsupported RRULE `{}`", rule));
+                return None;
+            }
+        };
+        if !ignored.is_empty() {
+            self.warn(vtodo, format!("ignored `{}` of the RRULE", ignored.join(";")));
+        }
+        Some(repeat)
+    }
+
+    /// The instant of a `DATE-TIME` or `DATE` property, a `DATE` being
+    /// taken at midnight.
+    fn time(&mut self, component: &Component, property: &Property) -> Option<DateTime<Utc>> {
+        let value = property.value.trim();
+        if value.ends_with('Z') {
+            if let Ok(time) = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ") {
+                return Some(DateTime::from_utc(time, Utc));
+            }
+        }
+
+        let local = if property.is_date() {
+            NaiveDate::parse_from_str(value, "%Y%m%d").map(|date| date.and_hms(0, 0, 0))
+        } else {
+            NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+        };
+        let local = match local {
+            Ok(local) => local,
+            Err(_) => {
+                self.warn(component, format!("ignored {} `{}`", property.name, value));
+                return None;
+            }
+        };
+
+        match property.param("TZID") {
+            Some(tzid) => {
+                match tzid.parse::<chrono_tz::Tz>() {
+                    Ok(tz) => Some(resolve(&tz, local)),
+                    Err(_) => {
+                        self.warn(component,
+                                  format!("unknown timezone `{}`, read as local time", tzid));
+                        Some(resolve(&self.tz, local))
+                    }
+                }
+            }
+            None => Some(resolve(&self.tz, local)),
+        }
+    }
+
+    /// Keeps the `record` if it is valid.
+    fn check<T: Validate>(&mut self, component: &Component, record: T) -> Option<T> {
+        let violations = record.validate();
+        if violations.is_empty() {
+            return Some(record);
+        }
+
+        let violations = violations.iter().map(|v| v.to_string()).collect::<Vec<_>>();
+        self.warn(component, format!("skipped, {}", violations.join(", ")));
+        None
+    }
+
+    fn warn(&mut self, component: &Component, message: String) {
+        let name = component.text("SUMMARY").or_else(|| component.text("UID")).unwrap_or_default();
+        self.warnings.push(format!("{} `{}`: {}", component.name, name, message));
+    }
+}
+
+impl Component {
+    fn property(&self, name: &str) -> Option<&Property> {
+        self.properties.iter().find(|property| property.name == name)
+    }
+
+    /// The unescaped value of the `TEXT` property `name`.
+    fn text(&self, name: &str) -> Option<String> {
+        self.property(name).map(|property| unescape(&property.value))
+    }
+
+    fn components(&self, name: &str) -> Vec<&Component> {
+        self.components.iter().filter(|component| component.name == name).collect()
+    }
+
+    /// The `UID` of the parent of a `VTODO`.
+    fn parent(&self) -> Option<String> {
+        self.properties
+            .iter()
+            .filter(|property| property.name == "RELATED-TO")
+            .filter(|property| property.param("RELTYPE").map_or(true, |t| t == "PARENT"))
+            .map(|property| property.value.trim().to_owned())
+            .next()
+    }
+
+    fn is_completed(&self) -> bool {
+        self.text("STATUS").map_or(false, |status| status == "COMPLETED") ||
+        self.property("COMPLETED").is_some()
+    }
+}
+
+impl Property {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|&&(ref n, _)| n == name).map(|&(_, ref value)| value.as_str())
+    }
+
+    fn is_date(&self) -> bool {
+        self.param("VALUE") == Some("DATE") || self.value.trim().len() == 8
+    }
+}
+
+fn event(pomo: &Pomo, lines: &mut Vec<String>) {
+    let abandoned = pomo.abandoned == Some(true);
+
+    lines.push("BEGIN:VEVENT".to_owned());
+    lines.push(format!("UID:{}", uid(pomo.uuid)));
+    lines.push(format!("DTSTAMP:{}", time(pomo.updated_at.unwrap_or(pomo.ended_at))));
+    lines.push(format!("DTSTART:{}", time(pomo.started_at)));
+    lines.push(format!("DTEND:{}", time(pomo.ended_at)));
+    lines.push(format!("SUMMARY:{}", escape(&pomo.description)));
+    if abandoned {
+        lines.push("STATUS:CANCELLED".to_owned());
+        lines.push("CATEGORIES:Pomo,Abandoned".to_owned());
+    } else {
+        lines.push("STATUS:CONFIRMED".to_owned());
+        lines.push("CATEGORIES:Pomo".to_owned());
+    }
+    lines.push("END:VEVENT".to_owned());
+}
+
+fn vtodo(todo: &Todo, tz: Option<chrono_tz::Tz>, lines: &mut Vec<String>) {
+    lines.push("BEGIN:VTODO".to_owned());
+    lines.push(format!("UID:{}", uid(todo.uuid)));
+    lines.push(format!("DTSTAMP:{}", time(todo.updated_at.unwrap_or_else(Utc::now))));
+    if let Some(created_at) = todo.created_at {
+        lines.push(format!("CREATED:{}", time(created_at)));
+    }
+    lines.push(format!("SUMMARY:{}", escape(&todo.description)));
+    if let Some(ref notice) = todo.notice {
+        lines.push(format!("DESCRIPTION:{}", escape(notice)));
+    }
+    if todo.pin == Some(true) {
+        lines.push("PRIORITY:1".to_owned());
+    }
+    status(todo.completed, todo.completed_at, lines);
+
+    if let Some(remind_time) = todo.remind_time {
+        let start = match tz {
+            Some(tz) => {
+                let local = remind_time.with_timezone(&tz).naive_local();
+                let value = format!(";TZID={}:{}", tz.name(), local.format("%Y%m%dT%H%M%S"));
+                lines.push(format!("DTSTART{}", value));
+                lines.push(format!("DUE{}", value));
+                local.date()
+            }
+            None => {
+                lines.push(format!("DTSTART:{}", time(remind_time)));
+                lines.push(format!("DUE:{}", time(remind_time)));
+                remind_time.naive_utc().date()
+            }
+        };
+        let rule = todo.repeat_type.as_ref().and_then(|repeat| rrule(repeat, start));
+        if let Some(rule) = rule {
+            lines.push(format!("RRULE:{}", rule));
+        }
+
+        lines.push("BEGIN:VALARM".to_owned());
+        lines.push("ACTION:DISPLAY".to_owned());
+        lines.push(format!("DESCRIPTION:{}", escape(&todo.description)));
+        lines.push("TRIGGER;RELATED=START:PT0S".to_owned());
+        lines.push("END:VALARM".to_owned());
+    }
+    lines.push("END:VTODO".to_owned());
+}
+
+/// The `VTIMEZONE` of `tz` from the year `first` through `last`. The tz
+/// database has no rules to write as `RRULE`s, so every offset change is
+/// found and listed on its own.
+fn vtimezone(tz: chrono_tz::Tz, first: i32, last: i32, lines: &mut Vec<String>) {
+    let offset = |at: NaiveDateTime| tz.offset_from_utc_datetime(&at).fix().local_minus_utc();
+    let start = NaiveDate::from_ymd(first, 1, 1).and_hms(0, 0, 0);
+    let end = NaiveDate::from_ymd(last + 1, 1, 1).and_hms(0, 0, 0);
+
+    lines.push("BEGIN:VTIMEZONE".to_owned());
+    lines.push(format!("TZID:{}", tz.name()));
+    let mut before = offset(start);
+    onset(start, before, before, lines);
+
+    let mut day = start;
+    while day < end {
+        let next = day + Duration::days(1);
+        let after = offset(next);
+        if after != before {
+            // Narrow down the second the offset changes at.
+            let (mut low, mut high) = (day, next);
+            while high - low > Duration::seconds(1) {
+                let middle = low + (high - low) / 2;
+                if offset(middle) == before {
+                    low = middle;
+                } else {
+                    high = middle;
+                }
+            }
+            onset(high, before, after, lines);
+            before = after;
+        }
+        day = next;
+    }
+    lines.push("END:VTIMEZONE".to_owned());
+}
+
+/// An observance of a `VTIMEZONE` from `at` in UTC, daylight saving time if
+/// the clocks go forward.
+fn onset(at: NaiveDateTime, from: i32, to: i32, lines: &mut Vec<String>) {
+    let name = if to > from { "DAYLIGHT" } else { "STANDARD" };
+    let local = at + Duration::seconds(from as i64);
+    lines.push(format!("BEGIN:{}", name));
+    lines.push(format!("DTSTART:{}", local.format("%Y%m%dT%H%M%S")));
+    lines.push(format!("TZOFFSETFROM:{}", utc_offset(from)));
+    lines.push(format!("TZOFFSETTO:{}", utc_offset(to)));
+    lines.push(format!("END:{}", name));
+}
+
+fn sub_vtodo(sub_todo: &SubTodo, lines: &mut Vec<String>) {
+    lines.push("BEGIN:VTODO".to_owned());
+    lines.push(format!("UID:{}", uid(sub_todo.uuid)));
+    lines.push(format!("DTSTAMP:{}", time(sub_todo.updated_at.unwrap_or_else(Utc::now))));
+    lines.push(format!("SUMMARY:{}", escape(&sub_todo.description)));
+    status(sub_todo.completed, sub_todo.completed_at, lines);
+    if let Some(parent) = sub_todo.parent_uuid {
+        lines.push(format!("RELATED-TO;RELTYPE=PARENT:{}", parent));
+    }
+    lines.push("END:VTODO".to_owned());
+}
+
+fn status(completed: Option<bool>, completed_at: Option<DateTime<Utc>>, lines: &mut Vec<String>) {
+    if completed == Some(true) {
+        lines.push("STATUS:COMPLETED".to_owned());
+        if let Some(completed_at) = completed_at {
+            lines.push(format!("COMPLETED:{}", time(completed_at)));
+        }
+    } else {
+        lines.push("STATUS:NEEDS-ACTION".to_owned());
+    }
+}
+
+/// The `RRULE` value repeating as `repeat` from the `start` day, the days
+/// past the 28th falling back to the last day of the shorter months.
+fn rrule(repeat: &RepeatType, start: NaiveDate) -> Option<String> {
+    let day = start.day();
+    let last_days = || (28..day + 1).map(|d| d.to_string()).collect::<Vec<_>>().join(",");
+
+    match *repeat {
+        RepeatType::None => None,
+        RepeatType::EachDay => Some("FREQ=DAILY".to_owned()),
+        RepeatType::EachWeek => Some("FREQ=WEEKLY".to_owned()),
+        RepeatType::EachTwoWeek => Some("FREQ=WEEKLY;INTERVAL=2".to_owned()),
+        RepeatType::EachMonth if day > 28 => {
+            Some(format!("FREQ=MONTHLY;BYMONTHDAY={};BYSETPOS=-1", last_days()))
+        }
+        RepeatType::EachMonth => Some("FREQ=MONTHLY".to_owned()),
+        RepeatType::EachYear if day > 28 => {
+            Some(format!("FREQ=YEARLY;BYMONTH={};BYMONTHDAY={};BYSETPOS=-1",
+                         start.month(),
+                         last_days()))
+        }
+        RepeatType::EachYear => Some("FREQ=YEARLY".to_owned()),
+    }
+}
+
+/// The `UID` of a record, a random one if it has no `uuid`.
+fn uid(uuid: Option<Uuid>) -> String {
+    uuid.unwrap_or_else(Uuid::new_v4).to_string()
+}
+
+/// A `DATE-TIME` value in UTC.
+fn time(time: DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// A `UTC-OFFSET` value from the seconds east of UTC.
+fn utc_offset(seconds: i32) -> String {
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let seconds = seconds.abs();
+    match seconds % 60 {
+        0 => format!("{}{:02}{:02}", sign, seconds / 3600, seconds / 60 % 60),
+        secs => format!("{}{:02}{:02}{:02}", sign, seconds / 3600, seconds / 60 % 60, secs),
+    }
+}
+
+/// Escapes a `TEXT` value.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Writes a content line, folded into lines of at most 75 octets without
+/// splitting a character.
+fn fold(line: &str, f: &mut fmt::Formatter) -> fmt::Result {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > LINE_LENGTH {
+            f.write_str("\r\n ")?;
+            width = 1;
+        }
+        f.write_char(c)?;
+        width += c.len_utf8();
+    }
+    f.write_str("\r\n")
+}
+
+/// Parses the iCalendar `text` into its `VCALENDAR`.
+fn parse(text: &str) -> Result<Component, Error> {
+    let mut stack: Vec<Component> = Vec::new();
+    for (number, line) in unfold(text) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let property = parse_line(&line)
+            .ok_or_else(|| Error::from(format!("invalid iCalendar line {}", number)))?;
+
+        let name = property.name.clone();
+        match name.as_str() {
+            "BEGIN" => {
+                stack.push(Component {
+                               name: property.value.trim().to_uppercase(),
+                               ..Default::default()
+                           })
+            }
+            "END" => {
+                let component = match stack.pop() {
+                    Some(component) => component,
+                    None => return Err(format!("unexpected END at line {}", number).into()),
+                };
+                if component.name != property.value.trim().to_uppercase() {
+                    return Err(format!("unexpected END at line {}", number).into());
+                }
+                match stack.last_mut() {
+                    Some(parent) => parent.components.push(component),
+                    None if component.name == "VCALENDAR" => return Ok(component),
+                    None => return Err("the calendar is not a VCALENDAR".into()),
+                }
+            }
+            _ => {
+                match stack.last_mut() {
+                    Some(component) => component.properties.push(property),
+                    None => return Err(format!("unexpected line {}", number).into()),
+                }
+            }
+        }
+    }
+
+    Err("the calendar has no VCALENDAR".into())
+}
+
+/// The content lines of `text` with their line numbers, joining the folded lines.
+fn unfold(text: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some(&mut (_, ref mut last)) = lines.last_mut() {
+                last.push_str(&line[1..]);
+                continue;
+            }
+        }
+        lines.push((index + 1, line.to_owned()));
+    }
+    lines
+}
+
+/// Splits a content line into its name, parameters and value, or `None`
+/// if it has no value.
+fn parse_line(line: &str) -> Option<Property> {
+    let mut quoted = false;
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => {
+                parts.push(&line[start..i]);
+                start = i + 1;
+            }
+            ':' if !quoted => {
+                parts.push(&line[start..i]);
+                let name = parts[0].trim().to_uppercase();
+                if name.is_empty() {
+                    return None;
+                }
+
+                let params = parts[1..]
+                    .iter()
+                    .map(|param| {
+                        let mut pair = param.splitn(2, '=');
+                        let name = pair.next().unwrap_or("").trim().to_uppercase();
+                        let value = pair.next().unwrap_or("").trim_matches('"').to_owned();
+                        (name, value)
+                    })
+                    .collect();
+                return Some(Property {
+                                name: name,
+                                params: params,
+                                value: line[i + 1..].to_owned(),
+                            });
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Unescapes a `TEXT` value.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') | Some('N') => unescaped.push('\n'),
+            Some(c) => unescaped.push(c),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+/// Parses a `DURATION` value, e.g. `-PT15M` or `P1DT2H`.
+fn duration(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    let (sign, value) = if value.starts_with('-') {
+        (-1, &value[1..])
+    } else {
+        (1, value.trim_left_matches('+'))
+    };
+    if !value.starts_with('P') || value.len() == 1 {
+        return None;
+    }
+
+    let mut total = Duration::zero();
+    let mut number = String::new();
+    let mut time = false;
+    for c in value[1..].chars() {
+        match c {
+            '0'...'9' => number.push(c),
+            'T' if number.is_empty() && !time => time = true,
+            _ => {
+                let n = number.parse::<i64>().ok()?;
+                number.clear();
+                let unit = match (c, time) {
+                    ('W', false) => 7 * 24 * 3600,
+                    ('D', false) => 24 * 3600,
+                    ('H', true) => 3600,
+                    ('M', true) => 60,
+                    ('S', true) => 1,
+                    _ => return None,
+                };
+                // Out of the range of a `Duration`, which panics.
+                let secs = n.checked_mul(unit)?;
+                if secs > Duration::max_value().num_seconds() {
+                    return None;
+                }
+                total = total.checked_add(&Duration::seconds(secs))?;
+            }
+        }
+    }
+
+    if number.is_empty() {
+        Some(total * sign)
+    } else {
+        None
+    }
+}
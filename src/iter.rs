@@ -0,0 +1,201 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::vec;
+
+use uuid::Uuid;
+use chrono::prelude::*;
+use chrono::Duration;
+
+use errors::*;
+use client::Client;
+use pomo::{Pomo, PomoParameter};
+use todo::{Todo, TodoParameter};
+
+/// An iterator walking every page of [`Pomo`](struct.Pomo.html)s,
+/// created by [`Client::pomos_iter`](struct.Client.html#method.pomos_iter).
+///
+/// The pages are requested lazily, from the latest `Pomo` to the earliest,
+/// each one up to the earliest `started_at` of the previous page. A page
+/// may repeat the `Pomo`s sharing that time, these are yielded only once.
+/// The iteration stops once a page has no new `Pomo` or after yielding an
+/// error.
+#[derive(Debug)]
+pub struct PomoIter<'a> {
+    client: &'a Client,
+    param: PomoParameter,
+    cursor: Cursor,
+    page: vec::IntoIter<Pomo>,
+    done: bool,
+}
+
+/// An iterator walking every page of [`Todo`](struct.Todo.html)s,
+/// created by [`Client::todos_iter`](struct.Client.html#method.todos_iter).
+///
+/// The pages are requested lazily, each one up to the earliest
+/// `completed_at` of the previous page. Uncompleted `Todo`s have
+/// no `completed_at` to page by, so a page without any completed `Todo` is
+/// the last one. The iteration also stops after yielding an error.
+#[derive(Debug)]
+pub struct TodoIter<'a> {
+    client: &'a Client,
+    param: TodoParameter,
+    cursor: Cursor,
+    page: vec::IntoIter<Todo>,
+    done: bool,
+}
+
+impl<'a> PomoIter<'a> {
+    pub(crate) fn new(client: &'a Client, param: PomoParameter) -> PomoIter<'a> {
+        PomoIter {
+            client: client,
+            param: param,
+            cursor: Cursor::default(),
+            page: Vec::new().into_iter(),
+            done: false,
+        }
+    }
+}
+
+impl<'a> TodoIter<'a> {
+    pub(crate) fn new(client: &'a Client, param: TodoParameter) -> TodoIter<'a> {
+        TodoIter {
+            client: client,
+            param: param,
+            cursor: Cursor::default(),
+            page: Vec::new().into_iter(),
+            done: false,
+        }
+    }
+}
+
+impl<'a> Iterator for PomoIter<'a> {
+    type Item = Result<Pomo, Error>;
+
+    fn next(&mut self) -> Option<Result<Pomo, Error>> {
+        loop {
+            if let Some(pomo) = self.page.next() {
+                return Some(Ok(pomo));
+            }
+            if self.done {
+                return None;
+            }
+
+            let page = match self.client.pomos(self.param.clone()) {
+                Ok(page) => page,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            };
+
+            let (page, bound) = self.cursor.turn(page, |pomo| (pomo.uuid, Some(pomo.started_at)));
+            match bound {
+                Some(bound) => {
+                    self.param.with_started_earlier(bound);
+                }
+                None => self.done = true,
+            }
+            self.page = page.into_iter();
+        }
+    }
+}
+
+impl<'a> Iterator for TodoIter<'a> {
+    type Item = Result<Todo, Error>;
+
+    fn next(&mut self) -> Option<Result<Todo, Error>> {
+        loop {
+            if let Some(todo) = self.page.next() {
+                return Some(Ok(todo));
+            }
+            if self.done {
+                return None;
+            }
+
+            let page = match self.client.todos(self.param.clone()) {
+                Ok(page) => page,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            };
+
+            let (page, bound) = self.cursor.turn(page, |todo| (todo.uuid, todo.completed_at));
+            match bound {
+                Some(bound) => {
+                    self.param.with_completed_earlier(bound);
+                }
+                None => self.done = true,
+            }
+            self.page = page.into_iter();
+        }
+    }
+}
+
+/// Where the next page of an iterator ends.
+///
+/// Server only takes an exclusive bound, so the items sharing the time of
+/// the earliest item of a page would be skipped. Instead the next page is
+/// requested up to a second later, and the items yielded already are left
+/// out. Only when a page brings nothing new the bound becomes exclusive, so
+/// the iteration moves past a run of items with the same time.
+#[derive(Debug, Default)]
+struct Cursor {
+    earliest: Option<DateTime<Utc>>,
+    exclusive: bool,
+    seen: Vec<(Uuid, DateTime<Utc>)>,
+}
+
+impl Cursor {
+    /// Leaves out the items of the `page` yielded already, returning the
+    /// new ones and the bound of the next page, `None` after the last page.
+    /// The `key` of an item is its `uuid` and the time paged by.
+    fn turn<T, F>(&mut self, page: Vec<T>, key: F) -> (Vec<T>, Option<DateTime<Utc>>)
+        where F: Fn(&T) -> (Option<Uuid>, Option<DateTime<Utc>>)
+    {
+        let fresh = page.into_iter()
+            .filter(|item| match key(item).0 {
+                        Some(uuid) => !self.seen.iter().any(|&(seen, _)| seen == uuid),
+                        None => true,
+                    })
+            .collect::<Vec<_>>();
+
+        if fresh.is_empty() {
+            return match self.earliest {
+                       Some(earliest) if !self.exclusive => {
+                           self.exclusive = true;
+                           (fresh, Some(earliest))
+                       }
+                       _ => (fresh, None),
+                   };
+        }
+
+        let earliest = match fresh.iter().filter_map(|item| key(item).1).min() {
+            Some(earliest) => earliest,
+            None => return (fresh, None),
+        };
+        let bound = earliest + Duration::seconds(1);
+
+        for item in &fresh {
+            if let (Some(uuid), Some(time)) = key(item) {
+                self.seen.push((uuid, time));
+            }
+        }
+        self.seen.retain(|&(_, time)| time < bound);
+        self.earliest = Some(earliest);
+        self.exclusive = false;
+
+        (fresh, Some(bound))
+    }
+}
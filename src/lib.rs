@@ -18,7 +18,14 @@
 extern crate uuid;
 extern crate serde;
 extern crate chrono;
+extern crate chrono_tz;
+extern crate rand;
+extern crate url;
 extern crate reqwest;
+#[cfg(feature = "async")]
+extern crate futures;
+#[cfg(feature = "async")]
+extern crate tokio_core;
 #[macro_use]
 extern crate serde_json;
 #[macro_use]
@@ -30,11 +37,46 @@ mod account;
 mod pomo;
 mod todo;
 mod client;
+mod transport;
+mod retry;
+mod ratelimit;
+mod iter;
+mod query;
+mod patch;
+mod validate;
+mod repeat;
+mod fs;
+#[cfg(feature = "async")]
+mod async_client;
+
+pub mod timer;
+pub mod store;
+pub mod sync;
+pub mod outbox;
+pub mod stats;
+pub mod tags;
+pub mod remind;
+pub mod ical;
+#[cfg(feature = "testing")]
+pub mod testing;
 
 pub use self::account::Account;
 pub use self::pomo::{Pomo, PomoBuilder, PomoParameter};
-pub use self::todo::{Todo, SubTodo, TodoBuilder, SubTodoBuilder, TodoParameter};
-pub use self::client::Client;
+pub use self::todo::{Todo, SubTodo, TodoBuilder, SubTodoBuilder, TodoParameter, RepeatType};
+pub use self::client::{Client, ClientBuilder};
+pub use self::transport::{Transport, HttpRequest, HttpResponse, ReqwestTransport};
+pub use self::retry::RetryPolicy;
+pub use self::ratelimit::RateLimiter;
+pub use self::iter::{PomoIter, TodoIter};
+pub use self::repeat::Occurrences;
+pub use self::query::Query;
+pub use self::patch::{Field, TodoPatch, SubTodoPatch, PomoPatch};
+pub use self::validate::{Validate, Violation};
+pub use self::tags::Tagged;
+pub use reqwest::{Method, Url};
+pub use chrono_tz::Tz;
+#[cfg(feature = "async")]
+pub use self::async_client::{AsyncClient, AsyncTransport, ReqwestAsyncTransport, ApiFuture};
 
 /// The Errors that may occur when communicating with Pomotodo server.
 pub mod errors {
@@ -45,6 +87,65 @@ pub mod errors {
 
         foreign_links {
             ReqError(::reqwest::Error);
+            UrlError(::reqwest::UrlError);
+            JsonError(::serde_json::Error);
+            IoError(::std::io::Error);
+        }
+
+        errors {
+            /// The payload was rejected before sending, see
+            /// [`Validate`](../trait.Validate.html).
+            Invalid(violations: Vec<::validate::Violation>) {
+                description("invalid payload")
+                display("invalid payload: {}",
+                        violations.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(", "))
+            }
+
+            /// The `timezone` of an [`Account`](../struct.Account.html) is not
+            /// in the tz database.
+            UnknownTimezone(name: String) {
+                description("unknown timezone")
+                display("unknown timezone `{}`", name)
+            }
+
+            /// A [`Timer`](../timer/struct.Timer.html) can not `action` in its `state`.
+            InvalidTransition { state: ::timer::State, action: &'static str } {
+                description("invalid timer transition")
+                display("can not {} while the timer is {:?}", action, state)
+            }
+
+            Unauthorized {
+                description("unauthorized")
+                display("the access token is missing, invalid or expired")
+            }
+
+            NotFound(uuid: ::uuid::Uuid) {
+                description("not found")
+                display("{} is not found", uuid)
+            }
+
+            /// The server rejected the payload. The `field` is empty if
+            /// the server did not tell which field is invalid.
+            Validation { field: String, message: String } {
+                description("validation failed")
+                display("invalid field `{}`: {}", field, message)
+            }
+
+            RateLimited { retry_after: Option<::std::time::Duration> } {
+                description("rate limited")
+                display("rate limited, retry after {:?}", retry_after)
+            }
+
+            Server(status: u16) {
+                description("server error")
+                display("server error with status {}", status)
+            }
+
+            /// Any other unexpected status, with the message decoded from the body.
+            Http(status: u16, message: String) {
+                description("unexpected status")
+                display("unexpected status {}: {}", status, message)
+            }
         }
     }
 }
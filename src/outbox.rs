@@ -0,0 +1,388 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A durable queue of the writes which could not reach Pomotodo server.
+//!
+//! The [`Outbox`](struct.Outbox.html) sends each write through a `Client`
+//! right away, and if server can not take the request, i.e. it fails with
+//! a `ReqError`, an `IoError`, a `Server` error or `RateLimited`, the write
+//! is saved to disk and [`replay`](struct.Outbox.html#method.replay)ed later,
+//! in order.
+//!
+//! A `Pomo` whose response was lost may have been stored by server anyway,
+//! so before submitting a queued `Pomo` again, the replay looks for the same
+//! one on server. A queued `Pomo` is not queued twice either.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! # use pomotodo::{Client, Pomo};
+//! # use pomotodo::outbox::{Outbox, Delivery};
+//! #
+//! # fn run() -> Result<(), pomotodo::errors::Error> {
+//! let client = Client::new("YOUR_ACCESS_TOKEN");
+//! let mut outbox = Outbox::open("outbox.json")?;
+//!
+//! match outbox.submit_pomo(&client, &Pomo::default())? {
+//!     Delivery::Sent(pomo) => println!("submitted {:?}", pomo.uuid),
+//!     Delivery::Queued => println!("offline, will submit later"),
+//! }
+//!
+//! // Once back online.
+//! let report = outbox.replay(&client)?;
+//! println!("sent {}, {} left", report.sent, outbox.len());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use uuid::Uuid;
+use chrono::Duration;
+use serde_json;
+
+use errors::*;
+use client::{Client, is_not_found};
+use fs::write_atomically;
+use patch::{Field, TodoPatch};
+use validate::ensure_valid;
+use pomo::{Pomo, PomoParameter};
+use todo::Todo;
+
+/// The writes waiting for connectivity, see the
+/// [module documentation](index.html).
+#[derive(Debug)]
+pub struct Outbox {
+    path: PathBuf,
+    operations: Vec<Operation>,
+}
+
+/// A write kept in an [`Outbox`](struct.Outbox.html).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum Operation {
+    SubmitPomo { pomo: Pomo },
+    CreateTodo { todo: Todo },
+    UpdateTodo { uuid: Uuid, patch: TodoPatch },
+    BumpTodo { uuid: Uuid },
+    DeletePomo { uuid: Uuid },
+    DeleteTodo { uuid: Uuid },
+    DeleteSubTodo { parent: Uuid, uuid: Uuid },
+}
+
+/// What became of a write sent through an [`Outbox`](struct.Outbox.html).
+#[derive(Debug)]
+pub enum Delivery<T> {
+    /// Server accepted it, with its response.
+    Sent(T),
+
+    /// Server could not be reached, it is queued.
+    Queued,
+}
+
+/// What a [`replay`](struct.Outbox.html#method.replay) did.
+#[derive(Debug, Default)]
+pub struct ReplayReport {
+    /// The operations accepted by server, or found already applied.
+    pub sent: usize,
+
+    /// The operations server refused, dropped from the queue.
+    pub rejected: Vec<(Operation, Error)>,
+}
+
+impl Outbox {
+    /// Opens the outbox kept at `path`, which is created on the first
+    /// queued operation.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Outbox, Error> {
+        let path = path.as_ref().to_path_buf();
+        let operations = if path.exists() {
+            serde_json::from_reader(File::open(&path)?)
+                .chain_err(|| format!("corrupt outbox {}", path.display()))?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Outbox {
+               path: path,
+               operations: operations,
+           })
+    }
+
+    /// The file keeping the queue.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The queued operations, oldest first.
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    /// The number of queued operations.
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    /// Whether nothing is queued.
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Submits a `Pomo`, or queues it if server can not be reached.
+    pub fn submit_pomo(&mut self, client: &Client, pomo: &Pomo) -> Result<Delivery<Pomo>, Error> {
+        ensure_valid(pomo)?;
+        if self.operations.iter().any(|op| op.is_submitting(pomo)) {
+            return Ok(Delivery::Queued);
+        }
+        self.deliver(client, Operation::SubmitPomo { pomo: pomo.clone() })
+            .map(|delivery| delivery.map(Reply::into_pomo))
+    }
+
+    /// Creates a `Todo`, or queues it if server can not be reached.
+    pub fn create_todo(&mut self, client: &Client, todo: &Todo) -> Result<Delivery<Todo>, Error> {
+        ensure_valid(todo)?;
+        self.deliver(client, Operation::CreateTodo { todo: todo.clone() })
+            .map(|delivery| delivery.map(Reply::into_todo))
+    }
+
+    /// Updates the `Todo` specified by `uuid`, or queues the `patch`
+    /// if server can not be reached.
+    pub fn update_todo<U: Into<Uuid>>(&mut self,
+                                      client: &Client,
+                                      uuid: U,
+                                      patch: &TodoPatch)
+                                      -> Result<Delivery<Todo>, Error> {
+        ensure_valid(patch)?;
+        let op = Operation::UpdateTodo {
+            uuid: uuid.into(),
+            patch: patch.clone(),
+        };
+        self.deliver(client, op).map(|delivery| delivery.map(Reply::into_todo))
+    }
+
+    /// Adds one to the `costed_pomo_count` of the `Todo` specified by
+    /// `uuid`, or queues it if server can not be reached. The count is
+    /// fetched when the operation is sent, not when it is queued.
+    pub fn bump_todo<U: Into<Uuid>>(&mut self,
+                                    client: &Client,
+                                    uuid: U)
+                                    -> Result<Delivery<Todo>, Error> {
+        self.deliver(client, Operation::BumpTodo { uuid: uuid.into() })
+            .map(|delivery| delivery.map(Reply::into_todo))
+    }
+
+    /// Deletes the `Pomo` specified by `uuid`, or queues it if server
+    /// can not be reached.
+    pub fn delete_pomo<U: Into<Uuid>>(&mut self,
+                                      client: &Client,
+                                      uuid: U)
+                                      -> Result<Delivery<()>, Error> {
+        self.deliver(client, Operation::DeletePomo { uuid: uuid.into() })
+            .map(|delivery| delivery.map(|_| ()))
+    }
+
+    /// Deletes the `Todo` specified by `uuid`, or queues it if server
+    /// can not be reached.
+    pub fn delete_todo<U: Into<Uuid>>(&mut self,
+                                      client: &Client,
+                                      uuid: U)
+                                      -> Result<Delivery<()>, Error> {
+        self.deliver(client, Operation::DeleteTodo { uuid: uuid.into() })
+            .map(|delivery| delivery.map(|_| ()))
+    }
+
+    /// Deletes the `SubTodo` specified by `uuid`, or queues it if server
+    /// can not be reached.
+    pub fn delete_subtodo<U: Into<Uuid>>(&mut self,
+                                         client: &Client,
+                                         parent: U,
+                                         uuid: U)
+                                         -> Result<Delivery<()>, Error> {
+        let op = Operation::DeleteSubTodo {
+            parent: parent.into(),
+            uuid: uuid.into(),
+        };
+        self.deliver(client, op).map(|delivery| delivery.map(|_| ()))
+    }
+
+    /// Sends the queued operations in order, stopping at the first one
+    /// which server still can not take.
+    ///
+    /// An operation refused by server is dropped and returned in the
+    /// [`ReplayReport`](struct.ReplayReport.html), since sending it again
+    /// would not help. Deleting a record already gone counts as sent.
+    pub fn replay(&mut self, client: &Client) -> Result<ReplayReport, Error> {
+        let mut report = ReplayReport::default();
+
+        while !self.operations.is_empty() {
+            let op = self.operations[0].clone();
+            match op.replay(client) {
+                Ok(_) => report.sent += 1,
+                Err(ref e) if is_unavailable(e) => break,
+                Err(e) => report.rejected.push((op, e)),
+            }
+
+            self.operations.remove(0);
+            self.save()?;
+        }
+
+        Ok(report)
+    }
+
+    /// Sends `op` right away, unless older operations are still queued,
+    /// so that the writes reach server in order.
+    fn deliver(&mut self, client: &Client, op: Operation) -> Result<Delivery<Reply>, Error> {
+        if self.operations.is_empty() {
+            match op.send(client) {
+                Ok(reply) => return Ok(Delivery::Sent(reply)),
+                Err(ref e) if is_unavailable(e) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.operations.push(op);
+        self.save()?;
+        Ok(Delivery::Queued)
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        write_atomically(&self.path, |file| Ok(serde_json::to_writer(file, &self.operations)?))
+    }
+}
+
+impl<T> Delivery<T> {
+    /// Whether the write is queued.
+    pub fn is_queued(&self) -> bool {
+        match *self {
+            Delivery::Queued => true,
+            Delivery::Sent(_) => false,
+        }
+    }
+
+    fn map<U, F: FnOnce(T) -> U>(self, f: F) -> Delivery<U> {
+        match self {
+            Delivery::Sent(value) => Delivery::Sent(f(value)),
+            Delivery::Queued => Delivery::Queued,
+        }
+    }
+}
+
+/// The response to an [`Operation`](enum.Operation.html).
+enum Reply {
+    Pomo(Pomo),
+    Todo(Todo),
+    Done,
+}
+
+impl Reply {
+    fn into_pomo(self) -> Pomo {
+        match self {
+            Reply::Pomo(pomo) => pomo,
+            _ => unreachable!(),
+        }
+    }
+
+    fn into_todo(self) -> Todo {
+        match self {
+            Reply::Todo(todo) => todo,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Operation {
+    fn send(&self, client: &Client) -> Result<Reply, Error> {
+        match *self {
+            Operation::SubmitPomo { ref pomo } => client.submit_pomo(pomo).map(Reply::Pomo),
+            Operation::CreateTodo { ref todo } => client.create_todo(todo).map(Reply::Todo),
+            Operation::UpdateTodo { uuid, ref patch } => {
+                client.update_todo(uuid, patch).map(Reply::Todo)
+            }
+            Operation::BumpTodo { uuid } => {
+                let costed = client.todo(uuid)?.costed_pomo_count.unwrap_or(0);
+                let patch = TodoPatch {
+                    costed_pomo_count: Field::Set(costed + 1),
+                    ..Default::default()
+                };
+                client.update_todo(uuid, &patch).map(Reply::Todo)
+            }
+            Operation::DeletePomo { uuid } => client.delete_pomo(uuid).map(|_| Reply::Done),
+            Operation::DeleteTodo { uuid } => client.delete_todo(uuid).map(|_| Reply::Done),
+            Operation::DeleteSubTodo { parent, uuid } => {
+                client.delete_subtodo(parent, uuid).map(|_| Reply::Done)
+            }
+        }
+    }
+
+    /// Sends a queued operation, skipping a `Pomo` already on server and
+    /// a delete of a record already gone.
+    fn replay(&self, client: &Client) -> Result<Reply, Error> {
+        if let Operation::SubmitPomo { ref pomo } = *self {
+            if let Some(submitted) = find_submitted(client, pomo)? {
+                return Ok(Reply::Pomo(submitted));
+            }
+        }
+
+        match self.send(client) {
+            Err(ref e) if self.is_delete() && is_not_found(e) => Ok(Reply::Done),
+            result => result,
+        }
+    }
+
+    fn is_delete(&self) -> bool {
+        match *self {
+            Operation::DeletePomo { .. } |
+            Operation::DeleteTodo { .. } |
+            Operation::DeleteSubTodo { .. } => true,
+            _ => false,
+        }
+    }
+
+    fn is_submitting(&self, pomo: &Pomo) -> bool {
+        match *self {
+            Operation::SubmitPomo { pomo: ref queued } => is_same_pomo(queued, pomo),
+            _ => false,
+        }
+    }
+}
+
+/// Looks for a `Pomo` on server started at the same time as `pomo`.
+fn find_submitted(client: &Client, pomo: &Pomo) -> Result<Option<Pomo>, Error> {
+    let mut param = PomoParameter::default();
+    param
+        .with_abandoned(pomo.abandoned.unwrap_or(false))
+        .with_manual(pomo.manual.unwrap_or(false))
+        .with_started_later(pomo.started_at - Duration::seconds(1))
+        .with_started_earlier(pomo.started_at + Duration::seconds(1));
+
+    Ok(client.pomos(param)?.into_iter().find(|submitted| is_same_pomo(submitted, pomo)))
+}
+
+/// The times are compared to the second, in case server drops the fraction.
+fn is_same_pomo(a: &Pomo, b: &Pomo) -> bool {
+    a.started_at.timestamp() == b.started_at.timestamp() &&
+    a.ended_at.timestamp() == b.ended_at.timestamp() && a.description == b.description
+}
+
+/// Whether server could not take the request for now, either it was not
+/// reached at all or it is down or busy.
+fn is_unavailable(error: &Error) -> bool {
+    match *error.kind() {
+        ErrorKind::ReqError(_) |
+        ErrorKind::IoError(_) |
+        ErrorKind::Server(_) |
+        ErrorKind::RateLimited { .. } => true,
+        _ => false,
+    }
+}
@@ -0,0 +1,170 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use chrono::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use todo::RepeatType;
+
+/// A field of a patch, which is left unchanged, set to a value or cleared.
+///
+/// An `Unchanged` field is not serialized at all, a `Clear`ed one is
+/// serialized as `null`, and the other way round for deserializing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Field<T> {
+    Unchanged,
+    Set(T),
+    Clear,
+}
+
+/// The changes to apply on a [`Todo`](struct.Todo.html),
+/// see [`Client::update_todo`](struct.Client.html#method.update_todo).
+///
+/// # Example
+///
+/// ```rust
+/// # use pomotodo::{Field, TodoPatch};
+/// let patch = TodoPatch {
+///     completed: Field::Set(true),
+///     remind_time: Field::Clear,
+///     ..Default::default()
+/// };
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TodoPatch {
+    #[serde(skip_serializing_if = "Field::is_unchanged")]
+    pub description: Field<String>,
+
+    #[serde(skip_serializing_if = "Field::is_unchanged")]
+    pub notice: Field<String>,
+
+    #[serde(skip_serializing_if = "Field::is_unchanged")]
+    pub pin: Field<bool>,
+
+    #[serde(skip_serializing_if = "Field::is_unchanged")]
+    pub completed: Field<bool>,
+
+    #[serde(skip_serializing_if = "Field::is_unchanged")]
+    pub completed_at: Field<DateTime<Utc>>,
+
+    #[serde(skip_serializing_if = "Field::is_unchanged")]
+    pub repeat_type: Field<RepeatType>,
+
+    #[serde(skip_serializing_if = "Field::is_unchanged")]
+    pub remind_time: Field<DateTime<Utc>>,
+
+    #[serde(skip_serializing_if = "Field::is_unchanged")]
+    pub estimated_pomo_count: Field<u64>,
+
+    #[serde(skip_serializing_if = "Field::is_unchanged")]
+    pub costed_pomo_count: Field<u64>,
+}
+
+/// The changes to apply on a [`SubTodo`](struct.SubTodo.html),
+/// see [`Client::update_subtodo`](struct.Client.html#method.update_subtodo).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SubTodoPatch {
+    #[serde(skip_serializing_if = "Field::is_unchanged")]
+    pub description: Field<String>,
+
+    #[serde(skip_serializing_if = "Field::is_unchanged")]
+    pub completed: Field<bool>,
+
+    #[serde(skip_serializing_if = "Field::is_unchanged")]
+    pub completed_at: Field<DateTime<Utc>>,
+}
+
+/// The changes to apply on a [`Pomo`](struct.Pomo.html),
+/// see [`Client::update_pomo`](struct.Client.html#method.update_pomo).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PomoPatch {
+    #[serde(skip_serializing_if = "Field::is_unchanged")]
+    pub description: Field<String>,
+}
+
+impl<T> Default for Field<T> {
+    fn default() -> Field<T> {
+        Field::Unchanged
+    }
+}
+
+impl<T> Field<T> {
+    /// Whether the field is left unchanged.
+    pub fn is_unchanged(&self) -> bool {
+        match *self {
+            Field::Unchanged => true,
+            _ => false,
+        }
+    }
+
+    /// The value the field is set to, if any.
+    pub fn value(&self) -> Option<&T> {
+        match *self {
+            Field::Set(ref value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+impl<T> From<Option<T>> for Field<T> {
+    fn from(value: Option<T>) -> Field<T> {
+        match value {
+            Some(value) => Field::Set(value),
+            None => Field::Clear,
+        }
+    }
+}
+
+impl<T: Serialize> Serialize for Field<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            Field::Set(ref value) => value.serialize(serializer),
+            Field::Unchanged | Field::Clear => serializer.serialize_none(),
+        }
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Field<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Field<T>, D::Error> {
+        Option::deserialize(deserializer).map(Field::from)
+    }
+}
+
+impl TodoPatch {
+    /// Whether the patch changes nothing.
+    pub fn is_empty(&self) -> bool {
+        *self == TodoPatch::default()
+    }
+}
+
+impl SubTodoPatch {
+    /// Whether the patch changes nothing.
+    pub fn is_empty(&self) -> bool {
+        *self == SubTodoPatch::default()
+    }
+}
+
+impl PomoPatch {
+    /// Constructs a `PomoPatch` setting the `description`.
+    pub fn description<S: Into<String>>(desc: S) -> PomoPatch {
+        PomoPatch { description: Field::Set(desc.into()) }
+    }
+
+    /// Whether the patch changes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.description.is_unchanged()
+    }
+}
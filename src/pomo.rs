@@ -11,8 +11,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::time::Duration as StdDuration;
+
 use uuid::Uuid;
 use chrono::prelude::*;
+use chrono::Duration;
+
+use errors::*;
+use query::Query;
+use account::Account;
+use validate::{ensure_valid, Violation};
 
 /// An `Pomo`.
 ///
@@ -49,11 +57,13 @@ pub struct Pomo {
 
     pub ended_at: DateTime<Utc>,
 
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub local_started_at: Option<DateTime<Utc>>,
+    /// The wall-clock time the `Pomo` started at where it was recorded.
+    #[serde(default, with = "local_time", skip_serializing_if = "Option::is_none")]
+    pub local_started_at: Option<NaiveDateTime>,
 
-    #[serde(skip_serializing_if = "Option::is_none")]
-    pub local_ended_at: Option<DateTime<Utc>>,
+    /// The wall-clock time the `Pomo` ended at where it was recorded.
+    #[serde(default, with = "local_time", skip_serializing_if = "Option::is_none")]
+    pub local_ended_at: Option<NaiveDateTime>,
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub length: Option<u64>,
@@ -66,13 +76,38 @@ pub struct Pomo {
 }
 
 /// A builder to construct the properties of a [`Pomo`](struct.Pomo.html).
+///
+/// The `started_at` defaults to now, and the `ended_at` is derived from
+/// `started_at + length` unless it is set explicitly.
+///
+/// # Example
+///
+/// ```rust
+/// # extern crate chrono;
+/// # extern crate pomotodo;
+/// # use chrono::prelude::*;
+/// # use pomotodo::Pomo;
+/// #
+/// # fn run() -> Result<(), pomotodo::errors::Error> {
+/// let mut builder = Pomo::builder();
+/// builder
+///     .description("Write the report")
+///     .started_at(Utc.ymd(2017, 1, 1).and_hms(9, 0, 0))
+///     .length(25 * 60);
+/// let pomo = builder.finish()?;
+/// assert_eq!(Utc.ymd(2017, 1, 1).and_hms(9, 25, 0), pomo.ended_at);
+/// # Ok(())
+/// # }
+/// # fn main() { run().unwrap() }
+/// ```
 #[derive(Debug)]
 pub struct PomoBuilder {
     pomo: Pomo,
+    ended_at: Option<DateTime<Utc>>,
 }
 
 /// The parameters used in getting [`Pomo`](struct.Pomo.html)s.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct PomoParameter {
     abandoned: Option<bool>,
     manual: Option<bool>,
@@ -118,7 +153,30 @@ impl Pomo {
     /// Creates an [`PomoBuilder`](struct.PomoBuilder.html)
     /// to configure a [`Pomo`](struct.Pomo.html).
     pub fn builder() -> PomoBuilder {
-        PomoBuilder { pomo: Pomo::default() }
+        PomoBuilder {
+            pomo: Pomo::default(),
+            ended_at: None,
+        }
+    }
+
+    /// The wall-clock start of the `Pomo`, the recorded `local_started_at`
+    /// if any, otherwise the `started_at` in `tz`.
+    pub fn local_start<Tz: TimeZone>(&self, tz: &Tz) -> NaiveDateTime {
+        self.local_started_at.unwrap_or_else(|| self.started_at.with_timezone(tz).naive_local())
+    }
+
+    /// The wall-clock end of the `Pomo`, the recorded `local_ended_at`
+    /// if any, otherwise the `ended_at` in `tz`.
+    pub fn local_end<Tz: TimeZone>(&self, tz: &Tz) -> NaiveDateTime {
+        self.local_ended_at.unwrap_or_else(|| self.ended_at.with_timezone(tz).naive_local())
+    }
+
+    /// The day the Pomotodo apps list the `Pomo` under for the `account`.
+    ///
+    /// Fails with `ErrorKind::UnknownTimezone` if the timezone of the
+    /// `account` is not in the tz database.
+    pub fn local_day(&self, account: &Account) -> Result<NaiveDate, Error> {
+        Ok(self.local_start(&account.tz()?).date())
     }
 }
 
@@ -129,9 +187,27 @@ impl PomoBuilder {
         self
     }
 
-    /// Set the `end_at` property.
+    /// Set the `end_at` property, which takes precedence over the `length`.
     pub fn ended_at(&mut self, time: DateTime<Utc>) -> &mut PomoBuilder {
-        self.pomo.ended_at = time;
+        self.ended_at = Some(time);
+        self
+    }
+
+    /// Set the `length` property in seconds.
+    pub fn length(&mut self, seconds: u64) -> &mut PomoBuilder {
+        self.pomo.length = Some(seconds);
+        self
+    }
+
+    /// Set the `local_started_at` property.
+    pub fn local_started_at(&mut self, time: NaiveDateTime) -> &mut PomoBuilder {
+        self.pomo.local_started_at = Some(time);
+        self
+    }
+
+    /// Set the `local_ended_at` property.
+    pub fn local_ended_at(&mut self, time: NaiveDateTime) -> &mut PomoBuilder {
+        self.pomo.local_ended_at = Some(time);
         self
     }
 
@@ -141,9 +217,40 @@ impl PomoBuilder {
         self
     }
 
+    /// Set the `abandoned` property.
+    pub fn abandoned(&mut self, abandoned: bool) -> &mut PomoBuilder {
+        self.pomo.abandoned = Some(abandoned);
+        self
+    }
+
     /// Build a [`Pomo`](struct.Pomo.html).
-    pub fn finish(self) -> Pomo {
-        self.pomo
+    ///
+    /// The `length` is recomputed from the `ended_at` if both are set.
+    ///
+    /// Fails with `ErrorKind::Invalid` if neither `ended_at` nor `length`
+    /// is set, the `length` is too large to end at any time, or the `Pomo`
+    /// breaks any other creation rule.
+    pub fn finish(self) -> Result<Pomo, Error> {
+        let mut pomo = self.pomo;
+        match (self.ended_at, pomo.length) {
+            (Some(ended_at), length) => {
+                pomo.ended_at = ended_at;
+                let seconds = (ended_at - pomo.started_at).num_seconds();
+                pomo.length = length.map(|_| seconds.max(0) as u64);
+            }
+            (None, Some(length)) => {
+                pomo.ended_at = Duration::from_std(StdDuration::from_secs(length))
+                    .ok()
+                    .and_then(|length| pomo.started_at.checked_add_signed(length))
+                    .ok_or_else(|| ErrorKind::Invalid(vec![Violation::OutOfRange("length")]))?;
+            }
+            (None, None) => {
+                return Err(ErrorKind::Invalid(vec![Violation::Required("ended_at")]).into())
+            }
+        }
+
+        ensure_valid(&pomo)?;
+        Ok(pomo)
     }
 }
 
@@ -184,30 +291,51 @@ impl PomoParameter {
         self
     }
 
-    /// Convert [`PomoParameter`](struct.PomoParameter.html) to query string.
-    pub fn to_query(&self) -> String {
-        let mut paras: Vec<String> = Vec::new();
+    /// Whether the `pomo` matches the parameters, as server filters it.
+    /// A missing `abandoned` or `manual` counts as `false`.
+    pub fn matches(&self, pomo: &Pomo) -> bool {
+        self.abandoned.map_or(true, |abandoned| pomo.abandoned.unwrap_or(false) == abandoned) &&
+        self.manual.map_or(true, |manual| pomo.manual.unwrap_or(false) == manual) &&
+        self.started_later_than.map_or(true, |than| pomo.started_at > than) &&
+        self.started_earlier_than.map_or(true, |than| pomo.started_at < than) &&
+        self.ended_later_than.map_or(true, |than| pomo.ended_at > than) &&
+        self.ended_earlier_than.map_or(true, |than| pomo.ended_at < than)
+    }
+
+    /// The exclusive bounds of `started_at`, later and earlier.
+    pub(crate) fn started_bounds(&self) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+        (self.started_later_than, self.started_earlier_than)
+    }
+
+    /// Convert [`PomoParameter`](struct.PomoParameter.html) to a typed [`Query`](struct.Query.html).
+    pub fn query(&self) -> Query {
+        let mut query = Query::new();
 
         if let Some(abandoned) = self.abandoned {
-            paras.push(format!("abandoned={}", abandoned));
+            query.push_bool("abandoned", abandoned);
         }
         if let Some(manual) = self.manual {
-            paras.push(format!("manual={}", manual));
+            query.push_bool("manual", manual);
         }
         if let Some(started_later_than) = self.started_later_than {
-            paras.push(format!("started_later_than={}", started_later_than));
+            query.push_time("started_later_than", started_later_than);
         }
         if let Some(started_earlier_than) = self.started_earlier_than {
-            paras.push(format!("started_earlier_than={}", started_earlier_than));
+            query.push_time("started_earlier_than", started_earlier_than);
         }
         if let Some(ended_later_than) = self.ended_later_than {
-            paras.push(format!("ended_later_than={}", ended_later_than));
+            query.push_time("ended_later_than", ended_later_than);
         }
         if let Some(ended_earlier_than) = self.ended_earlier_than {
-            paras.push(format!("ended_earlier_than={}", ended_earlier_than));
+            query.push_time("ended_earlier_than", ended_earlier_than);
         }
 
-        paras.join("&")
+        query
+    }
+
+    /// Convert [`PomoParameter`](struct.PomoParameter.html) to a percent-encoded query string.
+    pub fn to_query(&self) -> String {
+        self.query().to_string()
     }
 }
 
@@ -217,3 +345,45 @@ impl ::std::fmt::Display for Pomo {
         write!(f, "{}", to_string_pretty(self).unwrap_or_default())
     }
 }
+
+/// (De)serializes the local times of a `Pomo`.
+///
+/// Server sends them with a `Z` though they are not in UTC, so any offset
+/// is dropped and only the clock time is kept, while a time without any
+/// offset is accepted as well. They are written back with a `Z`.
+mod local_time {
+    use chrono::prelude::*;
+    use serde::{Deserialize, Deserializer, Serializer};
+    use serde::de::Error;
+
+    const FORMAT: &'static str = "%Y-%m-%dT%H:%M:%S%.fZ";
+
+    pub fn serialize<S>(time: &Option<NaiveDateTime>, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        match *time {
+            Some(ref time) => serializer.collect_str(&time.format(FORMAT)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<NaiveDateTime>, D::Error>
+        where D: Deserializer<'de>
+    {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(time) => {
+                parse(&time)
+                    .map(Some)
+                    .ok_or_else(|| D::Error::custom(format!("invalid local time `{}`", time)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn parse(time: &str) -> Option<NaiveDateTime> {
+        DateTime::parse_from_rfc3339(time)
+            .map(|time| time.naive_local())
+            .or_else(|_| time.parse::<NaiveDateTime>())
+            .ok()
+    }
+}
@@ -0,0 +1,78 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use chrono::prelude::*;
+use reqwest::Url;
+use url::form_urlencoded;
+
+/// The typed query parameters of a listing request.
+///
+/// Times are serialized in RFC 3339, e.g. `2017-01-01T09:00:00+00:00`, and
+/// every pair is percent-encoded when attached to a URL or converted to a
+/// string, so the `+` of an offset reaches the server as `%2B`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    pairs: Vec<(&'static str, String)>,
+}
+
+impl Query {
+    /// Constructs an empty `Query`.
+    pub fn new() -> Query {
+        Query::default()
+    }
+
+    /// Append a boolean parameter.
+    pub fn push_bool(&mut self, key: &'static str, value: bool) -> &mut Query {
+        self.pairs.push((key, value.to_string()));
+        self
+    }
+
+    /// Append a time parameter in RFC 3339.
+    pub fn push_time(&mut self, key: &'static str, value: DateTime<Utc>) -> &mut Query {
+        self.pairs.push((key, value.to_rfc3339()));
+        self
+    }
+
+    /// The parameters in order, not encoded.
+    pub fn pairs(&self) -> &[(&'static str, String)] {
+        &self.pairs
+    }
+
+    /// Whether there is no parameter.
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Append the encoded parameters to the query of `url`.
+    pub fn append_to(&self, url: &mut Url) {
+        if self.is_empty() {
+            return;
+        }
+
+        let mut query = url.query_pairs_mut();
+        for &(key, ref value) in &self.pairs {
+            query.append_pair(key, value);
+        }
+    }
+}
+
+impl ::std::fmt::Display for Query {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        let mut serializer = form_urlencoded::Serializer::new(String::new());
+        for &(key, ref value) in &self.pairs {
+            serializer.append_pair(key, value);
+        }
+
+        write!(f, "{}", serializer.finish())
+    }
+}
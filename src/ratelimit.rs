@@ -0,0 +1,178 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp;
+use std::thread;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use client::retry_after;
+use transport::HttpResponse;
+
+/// A token bucket pacing the requests of a [`Client`](struct.Client.html).
+///
+/// The bucket holds up to `capacity` tokens and is refilled at `per_second`
+/// tokens per second, every request takes one token and waits for it if the
+/// bucket is empty. Clones share the same bucket, so a limiter attached to a
+/// `Client` is shared by all its clones, and one limiter can be attached to
+/// many `Client`s using the same access token.
+///
+/// The limiter adapts to the server as well: a `429 Too Many Requests`
+/// pauses it for the `Retry-After`, and `X-RateLimit-Remaining` /
+/// `X-RateLimit-Reset` headers lower the available tokens.
+///
+/// # Example
+///
+/// ```rust
+/// # use pomotodo::{Client, RateLimiter};
+/// #
+/// # fn run() -> Result<(), pomotodo::errors::Error> {
+/// let limiter = RateLimiter::new(5, 1.0);
+///
+/// let mut builder = Client::builder("YOUR_ACCESS_TOKEN");
+/// builder.rate_limiter(limiter.clone());
+/// let client = builder.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    bucket: Arc<Mutex<Bucket>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    capacity: f64,
+    per_second: f64,
+    tokens: f64,
+    updated: Instant,
+    paused_until: Option<Instant>,
+}
+
+impl RateLimiter {
+    /// Constructs a `RateLimiter` allowing bursts of `capacity` requests,
+    /// refilled at `per_second` requests per second.
+    pub fn new(capacity: u32, per_second: f64) -> RateLimiter {
+        let capacity = cmp::max(capacity, 1) as f64;
+        let bucket = Bucket {
+            capacity: capacity,
+            per_second: per_second.max(::std::f64::MIN_POSITIVE),
+            tokens: capacity,
+            updated: Instant::now(),
+            paused_until: None,
+        };
+
+        RateLimiter { bucket: Arc::new(Mutex::new(bucket)) }
+    }
+
+    /// Blocks until a request is allowed to be sent.
+    pub fn acquire(&self) {
+        while let Some(wait) = self.try_acquire() {
+            thread::sleep(wait);
+        }
+    }
+
+    /// Takes a token if one is available, otherwise returns how long to wait.
+    pub fn try_acquire(&self) -> Option<Duration> {
+        let now = Instant::now();
+        let mut bucket = self.bucket();
+
+        let paused_until = bucket.paused_until;
+        if let Some(until) = paused_until {
+            if until > now {
+                return Some(until - now);
+            }
+            bucket.paused_until = None;
+        }
+
+        bucket.refill(now);
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            None
+        } else {
+            Some(seconds((1.0 - bucket.tokens) / bucket.per_second))
+        }
+    }
+
+    /// Stops handing out tokens for `duration`.
+    pub fn pause(&self, duration: Duration) {
+        let until = Instant::now() + duration;
+        let mut bucket = self.bucket();
+        let until = bucket.paused_until.map_or(until, |paused| cmp::max(paused, until));
+        bucket.tokens = 0.0;
+        bucket.paused_until = Some(until);
+    }
+
+    /// Adapts the bucket to the throttling reported by the server.
+    pub(crate) fn observe(&self, resp: &HttpResponse) {
+        if resp.status == 429 {
+            let wait = {
+                let bucket = self.bucket();
+                retry_after(resp).unwrap_or_else(|| seconds(1.0 / bucket.per_second))
+            };
+            return self.pause(wait);
+        }
+
+        let remaining = resp.header("X-RateLimit-Remaining")
+            .and_then(|remaining| remaining.trim().parse::<f64>().ok());
+        match remaining {
+            Some(remaining) if remaining < 1.0 => {
+                if let Some(reset) = resp.header("X-RateLimit-Reset").and_then(reset_after) {
+                    self.pause(reset);
+                }
+            }
+            Some(remaining) => {
+                let mut bucket = self.bucket();
+                bucket.tokens = bucket.tokens.min(remaining);
+            }
+            None => {}
+        }
+    }
+
+    fn bucket(&self) -> MutexGuard<Bucket> {
+        self.bucket.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        if now > self.updated {
+            let elapsed = now - self.updated;
+            let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 * 1e-9;
+            self.tokens = (self.tokens + elapsed * self.per_second).min(self.capacity);
+            self.updated = now;
+        }
+    }
+}
+
+/// Parses `X-RateLimit-Reset`, either seconds to wait or a UNIX timestamp.
+fn reset_after(value: &str) -> Option<Duration> {
+    let reset = match value.trim().parse::<u64>() {
+        Ok(reset) => reset,
+        Err(_) => return None,
+    };
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.as_secs()).unwrap_or(0);
+    if reset > now {
+        Some(Duration::from_secs(reset - now))
+    } else if reset < 1_000_000_000 {
+        Some(Duration::from_secs(reset))
+    } else {
+        None
+    }
+}
+
+fn seconds(secs: f64) -> Duration {
+    let secs = secs.max(0.0);
+    Duration::new(secs.trunc() as u64, (secs.fract() * 1e9) as u32)
+}
@@ -0,0 +1,377 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Local reminders for the `remind_time` of the open `Todo`s.
+//!
+//! [`Reminders`](struct.Reminders.html) schedules the `remind_time` of every
+//! loaded `Todo` and its recurrences from `repeat_type`, then hands each
+//! [`Reminder`](struct.Reminder.html) coming due to its
+//! [`Notifier`](trait.Notifier.html)s: any closure, the
+//! [`StdoutNotifier`](struct.StdoutNotifier.html), a
+//! [`CommandNotifier`](struct.CommandNotifier.html) or a
+//! [`WebhookNotifier`](struct.WebhookNotifier.html).
+//!
+//! The latest reminder fired for each `Todo` is kept in a small state file,
+//! so a restart neither fires them again nor misses the ones which came due
+//! meanwhile, unless they are older than the
+//! [grace period](struct.Reminders.html#method.with_grace).
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! # extern crate chrono;
+//! # extern crate pomotodo;
+//! # use chrono::{Duration, Utc};
+//! # use pomotodo::Client;
+//! # use pomotodo::remind::{Reminders, StdoutNotifier};
+//! #
+//! # fn run() -> Result<(), pomotodo::errors::Error> {
+//! let client = Client::new("YOUR_ACCESS_TOKEN");
+//!
+//! let mut reminders = Reminders::open("reminders.json", Utc)?;
+//! reminders.add_notifier(StdoutNotifier);
+//! reminders.run(&client, Duration::minutes(5), |e| eprintln!("reminders: {}", e))?;
+//! # Ok(())
+//! # }
+//! # fn main() {}
+//! ```
+
+use std::cmp;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Arc;
+use std::thread;
+
+use uuid::Uuid;
+use chrono::prelude::*;
+use chrono::Duration;
+use reqwest::{Method, Url};
+use serde_json;
+
+use errors::*;
+use client::Client;
+use fs::write_atomically;
+use todo::{Todo, TodoParameter};
+use transport::{Transport, HttpRequest, ReqwestTransport};
+
+/// Schedules and fires the reminders of `Todo`s in the timezone `Tz`,
+/// see the [module documentation](index.html).
+pub struct Reminders<Tz: TimeZone> {
+    path: PathBuf,
+    fired: HashMap<Uuid, DateTime<Utc>>,
+    todos: Vec<Todo>,
+    tz: Tz,
+    grace: Duration,
+    notifiers: Vec<Box<Notifier>>,
+}
+
+/// A `Todo` coming due.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Reminder {
+    pub uuid: Uuid,
+
+    pub description: String,
+
+    /// When it is due, the `remind_time` or one of its recurrences.
+    pub at: DateTime<Utc>,
+}
+
+/// What a [`fire`](struct.Reminders.html#method.fire) did.
+#[derive(Debug, Default)]
+pub struct FireReport {
+    /// The reminders due, recorded as fired.
+    pub fired: Vec<Reminder>,
+
+    /// The reminders a notifier failed to deliver, with its error.
+    pub failed: Vec<(Reminder, Error)>,
+}
+
+/// Delivers the [`Reminder`](struct.Reminder.html)s, implemented by any
+/// `Fn(&Reminder) -> Result<(), Error>` closure.
+pub trait Notifier {
+    fn notify(&self, reminder: &Reminder) -> Result<(), Error>;
+}
+
+/// Prints the reminders to stdout.
+#[derive(Debug, Clone, Copy)]
+pub struct StdoutNotifier;
+
+/// Runs a command for each reminder, with the `POMOTODO_UUID`,
+/// `POMOTODO_DESCRIPTION` and `POMOTODO_REMIND_TIME` environment variables.
+#[derive(Debug, Clone)]
+pub struct CommandNotifier {
+    program: String,
+    args: Vec<String>,
+}
+
+/// Posts each reminder as json to a URL, e.g. a local notification daemon.
+#[derive(Debug, Clone)]
+pub struct WebhookNotifier {
+    url: Url,
+    transport: Arc<Transport>,
+}
+
+impl<F: Fn(&Reminder) -> Result<(), Error>> Notifier for F {
+    fn notify(&self, reminder: &Reminder) -> Result<(), Error> {
+        self(reminder)
+    }
+}
+
+impl Notifier for StdoutNotifier {
+    fn notify(&self, reminder: &Reminder) -> Result<(), Error> {
+        let stdout = ::std::io::stdout();
+        let mut out = stdout.lock();
+        writeln!(out,
+                 "{} {}",
+                 reminder.at.format("%Y-%m-%d %H:%M"),
+                 reminder.description)?;
+        Ok(())
+    }
+}
+
+impl CommandNotifier {
+    /// Constructs a `CommandNotifier` running `program`.
+    pub fn new<S: Into<String>>(program: S) -> CommandNotifier {
+        CommandNotifier {
+            program: program.into(),
+            args: Vec::new(),
+        }
+    }
+
+    /// Add an argument to the command.
+    pub fn arg<S: Into<String>>(&mut self, arg: S) -> &mut CommandNotifier {
+        self.args.push(arg.into());
+        self
+    }
+}
+
+impl Notifier for CommandNotifier {
+    fn notify(&self, reminder: &Reminder) -> Result<(), Error> {
+        let status = Command::new(&self.program)
+            .args(&self.args)
+            .env("POMOTODO_UUID", reminder.uuid.to_string())
+            .env("POMOTODO_DESCRIPTION", &reminder.description)
+            .env("POMOTODO_REMIND_TIME", reminder.at.to_rfc3339())
+            .status()?;
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(format!("`{}` failed with {}", self.program, status).into())
+        }
+    }
+}
+
+impl WebhookNotifier {
+    /// Constructs a `WebhookNotifier` posting to `url`.
+    pub fn new(url: &str) -> Result<WebhookNotifier, Error> {
+        Ok(WebhookNotifier {
+               url: Url::parse(url)?,
+               transport: Arc::new(ReqwestTransport::new()),
+           })
+    }
+
+    /// Set the `Transport` sending the requests.
+    pub fn transport<T: Transport + 'static>(&mut self, transport: T) -> &mut WebhookNotifier {
+        self.transport = Arc::new(transport);
+        self
+    }
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify(&self, reminder: &Reminder) -> Result<(), Error> {
+        let request = HttpRequest {
+            method: Method::Post,
+            url: self.url.clone(),
+            headers: vec![("Content-Type".to_owned(), "application/json".to_owned())],
+            body: Some(serde_json::to_vec(reminder)?),
+        };
+
+        let resp = self.transport.send(request)?;
+        match resp.status {
+            200...299 => Ok(()),
+            status => {
+                let message = String::from_utf8_lossy(&resp.body).into_owned();
+                Err(ErrorKind::Http(status, message).into())
+            }
+        }
+    }
+}
+
+impl<Tz: TimeZone> ::std::fmt::Debug for Reminders<Tz> {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.debug_struct("Reminders")
+            .field("path", &self.path)
+            .field("fired", &self.fired)
+            .field("todos", &self.todos)
+            .field("grace", &self.grace)
+            .field("notifiers", &self.notifiers.len())
+            .finish()
+    }
+}
+
+impl<Tz: TimeZone> Reminders<Tz> {
+    /// Opens the reminders whose state is kept at `path`, scheduled in
+    /// the timezone `tz`, which should be the timezone of the account.
+    pub fn open<P: AsRef<Path>>(path: P, tz: Tz) -> Result<Reminders<Tz>, Error> {
+        let path = path.as_ref().to_path_buf();
+        let fired = if path.exists() {
+            serde_json::from_reader(File::open(&path)?)
+                .chain_err(|| format!("corrupt reminder state {}", path.display()))?
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Reminders {
+               path: path,
+               fired: fired,
+               todos: Vec::new(),
+               tz: tz,
+               grace: Duration::hours(1),
+               notifiers: Vec::new(),
+           })
+    }
+
+    /// Set how late a missed reminder may still be fired, one hour by default.
+    pub fn with_grace(&mut self, grace: Duration) -> &mut Reminders<Tz> {
+        self.grace = grace;
+        self
+    }
+
+    /// Add a [`Notifier`](trait.Notifier.html) to hand the reminders to.
+    pub fn add_notifier<N: Notifier + 'static>(&mut self, notifier: N) -> &mut Reminders<Tz> {
+        self.notifiers.push(Box::new(notifier));
+        self
+    }
+
+    /// Replaces the scheduled `Todo`s by the uncompleted ones of `todos`
+    /// with a `remind_time`.
+    pub fn load(&mut self, todos: &[Todo]) {
+        self.todos = todos
+            .iter()
+            .filter(|todo| todo.completed != Some(true))
+            .filter(|todo| todo.uuid.is_some() && todo.remind_time.is_some())
+            .cloned()
+            .collect();
+    }
+
+    /// Loads the uncompleted `Todo`s from server, walking every page.
+    pub fn refresh(&mut self, client: &Client) -> Result<(), Error> {
+        let todos = client
+            .todos_iter(TodoParameter::default())
+            .collect::<Result<Vec<_>, _>>()?;
+        self.load(&todos);
+        Ok(())
+    }
+
+    /// The reminders due at `now` and not fired yet, at most one for each
+    /// `Todo`, skipping the ones older than the grace period.
+    pub fn due(&self, now: DateTime<Utc>) -> Vec<Reminder> {
+        let mut due = Vec::new();
+        for todo in &self.todos {
+            let uuid = todo.uuid.expect("loaded todos have a uuid");
+            let fired = self.fired.get(&uuid).cloned();
+
+            let at = match self.times(todo).take_while(|time| *time <= now).last() {
+                Some(at) => at,
+                None => continue,
+            };
+            if Some(at) > fired && now - at <= self.grace {
+                due.push(Reminder {
+                             uuid: uuid,
+                             description: todo.description.clone(),
+                             at: at,
+                         });
+            }
+        }
+
+        due.sort_by_key(|reminder| reminder.at);
+        due
+    }
+
+    /// When the next reminder after `now` comes due, if any.
+    pub fn next_due(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.todos
+            .iter()
+            .filter_map(|todo| self.times(todo).find(|time| *time > now))
+            .min()
+    }
+
+    /// Hands the reminders due at `now` to every notifier.
+    ///
+    /// A reminder is recorded as fired even if a notifier failed, so the
+    /// ones which took it are not notified twice, the failures are listed in
+    /// the report instead. Only fails if the state can not be saved.
+    pub fn fire(&mut self, now: DateTime<Utc>) -> Result<FireReport, Error> {
+        let mut report = FireReport::default();
+        for reminder in self.due(now) {
+            for notifier in &self.notifiers {
+                if let Err(e) = notifier.notify(&reminder) {
+                    report.failed.push((reminder.clone(), e));
+                }
+            }
+            self.fired.insert(reminder.uuid, reminder.at);
+            self.save()?;
+            report.fired.push(reminder);
+        }
+        Ok(report)
+    }
+
+    /// Fires the reminders as they come due, loading the `Todo`s from
+    /// server every `refresh`. Only returns if the state can not be saved.
+    ///
+    /// A failed refresh or notifier is handed to `on_error`, the `Todo`s
+    /// loaded before are kept until the next refresh.
+    pub fn run<F>(&mut self,
+                  client: &Client,
+                  refresh: Duration,
+                  mut on_error: F)
+                  -> Result<(), Error>
+        where F: FnMut(Error)
+    {
+        loop {
+            if let Err(e) = self.refresh(client) {
+                on_error(e);
+            }
+            let until = Utc::now() + refresh;
+
+            loop {
+                let now = Utc::now();
+                for (_, e) in self.fire(now)?.failed {
+                    on_error(e);
+                }
+                if now >= until {
+                    break;
+                }
+
+                let wake = self.next_due(now).map_or(until, |due| cmp::min(due, until));
+                let sleep = (wake - now).to_std().unwrap_or_default();
+                thread::sleep(sleep);
+            }
+        }
+    }
+
+    /// The `remind_time` of the `todo` followed by its recurrences.
+    fn times<'a>(&'a self, todo: &Todo) -> Box<Iterator<Item = DateTime<Utc>> + 'a> {
+        let recurrences = todo.occurrences(self.tz.clone()).into_iter().flat_map(|times| times);
+        Box::new(iter::once(todo.remind_time).filter_map(|time| time).chain(recurrences))
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        write_atomically(&self.path, |file| Ok(serde_json::to_writer(file, &self.fired)?))
+    }
+}
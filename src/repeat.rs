@@ -0,0 +1,162 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp;
+
+use chrono::prelude::*;
+use chrono::Duration;
+
+use todo::{Todo, RepeatType};
+
+/// An iterator over the occurrences of a repeating [`Todo`](struct.Todo.html)
+/// after its `remind_time`, see [`Todo::occurrences`](struct.Todo.html#method.occurrences).
+///
+/// The occurrences are counted from the `remind_time` in local time, so
+/// they keep the same local time of day across DST changes. A monthly
+/// `Todo` on the 31st falls on the last day of the shorter months, and
+/// a yearly one on 29 February falls on 28 February of the common years.
+#[derive(Debug, Clone)]
+pub struct Occurrences<Tz: TimeZone> {
+    tz: Tz,
+    repeat: RepeatType,
+    anchor: NaiveDateTime,
+    index: u32,
+}
+
+impl<Tz: TimeZone> Iterator for Occurrences<Tz> {
+    type Item = DateTime<Utc>;
+
+    fn next(&mut self) -> Option<DateTime<Utc>> {
+        self.index = self.index.checked_add(1)?;
+        let local = self.repeat.nth(self.anchor, self.index)?;
+        Some(resolve(&self.tz, local))
+    }
+}
+
+impl RepeatType {
+    /// The `n`th occurrence after the local time `anchor`,
+    /// or `None` if it does not repeat.
+    fn nth(&self, anchor: NaiveDateTime, n: u32) -> Option<NaiveDateTime> {
+        match *self {
+            RepeatType::None => None,
+            RepeatType::EachDay => Some(anchor + Duration::days(n as i64)),
+            RepeatType::EachWeek => Some(anchor + Duration::weeks(n as i64)),
+            RepeatType::EachTwoWeek => Some(anchor + Duration::weeks(2 * n as i64)),
+            RepeatType::EachMonth => Some(add_months(anchor, n as i64)),
+            RepeatType::EachYear => Some(add_months(anchor, 12 * n as i64)),
+        }
+    }
+}
+
+impl Todo {
+    /// The occurrences after the `remind_time` in the timezone `tz`, which
+    /// should be the timezone of the account, or `None` if the `Todo` does
+    /// not repeat or has no `remind_time`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # extern crate chrono;
+    /// # extern crate pomotodo;
+    /// # use chrono::{TimeZone, Utc};
+    /// # use pomotodo::{Todo, RepeatType};
+    /// #
+    /// # fn main() {
+    /// let todo = Todo {
+    ///     repeat_type: Some(RepeatType::EachMonth),
+    ///     remind_time: Some(Utc.ymd(2017, 1, 31).and_hms(9, 0, 0)),
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let next = todo.occurrences(Utc).unwrap().take(2).collect::<Vec<_>>();
+    /// assert_eq!(vec![Utc.ymd(2017, 2, 28).and_hms(9, 0, 0),
+    ///                 Utc.ymd(2017, 3, 31).and_hms(9, 0, 0)],
+    ///            next);
+    /// # }
+    /// ```
+    pub fn occurrences<Tz: TimeZone>(&self, tz: Tz) -> Option<Occurrences<Tz>> {
+        match (&self.repeat_type, self.remind_time) {
+            (&Some(RepeatType::None), _) |
+            (&None, _) |
+            (_, None) => None,
+            (&Some(ref repeat), Some(remind_time)) => {
+                Some(Occurrences {
+                         anchor: remind_time.with_timezone(&tz).naive_local(),
+                         tz: tz,
+                         repeat: repeat.clone(),
+                         index: 0,
+                     })
+            }
+        }
+    }
+
+    /// The `Todo` to create once this repeating one is completed, or `None`
+    /// if it does not repeat.
+    ///
+    /// The follow-up copies the description, notice, pin, repeat type and
+    /// estimation, and is reminded at the first occurrence after both the
+    /// `remind_time` and the `completed_at`, in the timezone `tz`.
+    pub fn follow_up<Tz: TimeZone>(&self, tz: Tz) -> Option<Todo> {
+        match self.repeat_type {
+            None | Some(RepeatType::None) => return None,
+            _ => {}
+        }
+
+        let remind_time = match (self.occurrences(tz), self.remind_time) {
+            (Some(occurrences), Some(remind_time)) => {
+                let after = cmp::max(Some(remind_time), self.completed_at);
+                occurrences.skip_while(|time| Some(*time) <= after).next()
+            }
+            _ => None,
+        };
+
+        Some(Todo {
+                 description: self.description.clone(),
+                 notice: self.notice.clone(),
+                 pin: self.pin,
+                 repeat_type: self.repeat_type.clone(),
+                 remind_time: remind_time,
+                 estimated_pomo_count: self.estimated_pomo_count,
+                 ..Default::default()
+             })
+    }
+}
+
+/// Adds `months` to `time`, clamping the day to the end of the month.
+fn add_months(time: NaiveDateTime, months: i64) -> NaiveDateTime {
+    let total = time.year() as i64 * 12 + time.month0() as i64 + months;
+    let year = (total / 12) as i32;
+    let month = (total % 12) as u32 + 1;
+    let day = cmp::min(time.day(), days_in_month(year, month));
+
+    NaiveDate::from_ymd(year, month, day).and_time(time.time())
+}
+
+fn days_in_month(year: i32, month: u32) -> u32 {
+    let next = if month == 12 {
+        NaiveDate::from_ymd(year + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd(year, month + 1, 1)
+    };
+    next.pred().day()
+}
+
+/// The instant of the `local` time in `tz`. An ambiguous time takes its
+/// earlier instant, and a time skipped by a DST change is moved an hour on.
+pub(crate) fn resolve<Tz: TimeZone>(tz: &Tz, local: NaiveDateTime) -> DateTime<Utc> {
+    tz.from_local_datetime(&local)
+        .earliest()
+        .or_else(|| tz.from_local_datetime(&(local + Duration::hours(1))).earliest())
+        .map(|time| time.with_timezone(&Utc))
+        .unwrap_or_else(|| DateTime::from_utc(local, Utc))
+}
@@ -0,0 +1,179 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cmp;
+use std::time::Duration;
+
+use rand;
+use reqwest::Method;
+
+use errors::*;
+
+/// The policy deciding whether and when the [`Client`](struct.Client.html)
+/// retries a failed request.
+///
+/// The delay before the `n`th retry is `base * 2^(n - 1)`, capped at `cap`
+/// and, if `jitter` is enabled, randomized between half and all of it.
+/// A `Retry-After` sent with a `429 Too Many Requests` is used instead,
+/// capped at `cap` as well.
+///
+/// `GET`, `DELETE` and `PATCH` requests are retried by default, `POST`
+/// only if [`with_retry_post`](#method.with_retry_post) is set, since a
+/// retried submission may create the same `Pomo` or `Todo` twice.
+///
+/// Only the blocking `Client` retries, the `AsyncClient` makes one attempt.
+///
+/// # Example
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use pomotodo::{Client, RetryPolicy};
+/// #
+/// # fn run() -> Result<(), pomotodo::errors::Error> {
+/// let mut policy = RetryPolicy::default();
+/// policy.with_max_attempts(5).with_base(Duration::from_secs(1));
+///
+/// let mut builder = Client::builder("YOUR_ACCESS_TOKEN");
+/// builder.retry(policy);
+/// let client = builder.finish()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base: Duration,
+    cap: Duration,
+    jitter: bool,
+    statuses: Vec<u16>,
+    retry_io: bool,
+    retry_post: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base: Duration::from_millis(500),
+            cap: Duration::from_secs(30),
+            jitter: true,
+            statuses: vec![429, 500, 502, 503, 504],
+            retry_io: true,
+            retry_post: false,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A policy making exactly one attempt, the default of a `Client`.
+    pub fn never() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Default::default()
+        }
+    }
+
+    /// Set the maximum number of attempts, including the first one.
+    pub fn with_max_attempts(&mut self, attempts: u32) -> &mut RetryPolicy {
+        self.max_attempts = cmp::max(attempts, 1);
+        self
+    }
+
+    /// Set the delay before the first retry.
+    pub fn with_base(&mut self, base: Duration) -> &mut RetryPolicy {
+        self.base = base;
+        self
+    }
+
+    /// Set the upper bound of the delay between two attempts.
+    pub fn with_cap(&mut self, cap: Duration) -> &mut RetryPolicy {
+        self.cap = cap;
+        self
+    }
+
+    /// Set whether the delays are randomized.
+    pub fn with_jitter(&mut self, jitter: bool) -> &mut RetryPolicy {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Set the HTTP status codes worth a retry.
+    pub fn with_statuses(&mut self, statuses: Vec<u16>) -> &mut RetryPolicy {
+        self.statuses = statuses;
+        self
+    }
+
+    /// Set whether to retry when the request could not be delivered,
+    /// e.g. the connection was refused or reset.
+    pub fn with_retry_io(&mut self, retry: bool) -> &mut RetryPolicy {
+        self.retry_io = retry;
+        self
+    }
+
+    /// Set whether `POST` requests are retried as well.
+    pub fn with_retry_post(&mut self, retry: bool) -> &mut RetryPolicy {
+        self.retry_post = retry;
+        self
+    }
+
+    /// Whether the `attempt`th try of a `method` request failed
+    /// with `error` should be followed by another one.
+    pub(crate) fn permits(&self, method: &Method, attempt: u32, error: &Error) -> bool {
+        attempt < self.max_attempts && self.retries(method) && self.is_transient(error)
+    }
+
+    /// The delay before the retry following the `attempt`th try failed with `error`.
+    pub(crate) fn delay(&self, attempt: u32, error: &Error) -> Duration {
+        if let ErrorKind::RateLimited { retry_after: Some(after) } = *error.kind() {
+            return cmp::min(after, self.cap);
+        }
+
+        let factor = 1u32.checked_shl(attempt.saturating_sub(1)).unwrap_or(u32::max_value());
+        let delay = self.base
+            .checked_mul(factor)
+            .map_or(self.cap, |delay| cmp::min(delay, self.cap));
+
+        if self.jitter {
+            let half = delay / 2;
+            half + scale(delay - half, rand::random::<f64>())
+        } else {
+            delay
+        }
+    }
+
+    fn retries(&self, method: &Method) -> bool {
+        match *method {
+            Method::Get | Method::Head | Method::Options | Method::Delete | Method::Patch => true,
+            Method::Post => self.retry_post,
+            _ => false,
+        }
+    }
+
+    fn is_transient(&self, error: &Error) -> bool {
+        match *error.kind() {
+            ErrorKind::RateLimited { .. } => self.statuses.contains(&429),
+            ErrorKind::Server(status) |
+            ErrorKind::Http(status, _) => self.statuses.contains(&status),
+            ErrorKind::ReqError(_) |
+            ErrorKind::IoError(_) => self.retry_io,
+            _ => false,
+        }
+    }
+}
+
+/// Multiplies `duration` by `factor` in `[0, 1)`.
+fn scale(duration: Duration, factor: f64) -> Duration {
+    let nanos = duration.as_secs() as f64 * 1e9 + duration.subsec_nanos() as f64;
+    let scaled = (nanos * factor) as u64;
+    Duration::new(scaled / 1_000_000_000, (scaled % 1_000_000_000) as u32)
+}
@@ -0,0 +1,223 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Statistics over a list of `Pomo`s, e.g. for a weekly report.
+//!
+//! Every `Pomo` is bucketed by the local date and hour it started at, as
+//! recorded in its `local_started_at`, or else its `started_at` in the
+//! timezone given to [`Stats`](struct.Stats.html), which should be the
+//! timezone of the account, so the days match what the Pomotodo apps show.
+//! The focus time adds up the spans from `started_at` to `ended_at` of the
+//! finished `Pomo`s, the abandoned ones only count towards the
+//! [`abandonment_rate`](struct.Stats.html#method.abandonment_rate).
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! # extern crate chrono;
+//! # extern crate pomotodo;
+//! # use chrono::FixedOffset;
+//! # use pomotodo::{Client, PomoParameter};
+//! # use pomotodo::stats::Stats;
+//! #
+//! # fn run() -> Result<(), pomotodo::errors::Error> {
+//! let client = Client::new("YOUR_ACCESS_TOKEN");
+//! let pomos = client.pomos(PomoParameter::default())?;
+//!
+//! let stats = Stats::new(&pomos, FixedOffset::east(8 * 3600));
+//! for (week, bucket) in stats.per_week() {
+//!     println!("{}: {} pomos, {} minutes", week, bucket.count, bucket.focus.num_minutes());
+//! }
+//! # Ok(())
+//! # }
+//! # fn main() {}
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::prelude::*;
+use chrono::Duration;
+
+use chrono_tz;
+
+use errors::*;
+use pomo::Pomo;
+use account::Account;
+
+/// Statistics over a slice of `Pomo`s in the timezone `Tz`,
+/// see the [module documentation](index.html).
+#[derive(Debug, Clone)]
+pub struct Stats<'a, Tz: TimeZone> {
+    pomos: &'a [Pomo],
+    tz: Tz,
+}
+
+/// The finished `Pomo`s of a day, week or month.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bucket {
+    /// The number of finished `Pomo`s.
+    pub count: usize,
+
+    /// Their total length.
+    pub focus: Duration,
+}
+
+impl Default for Bucket {
+    fn default() -> Bucket {
+        Bucket {
+            count: 0,
+            focus: Duration::zero(),
+        }
+    }
+}
+
+impl<'a> Stats<'a, chrono_tz::Tz> {
+    /// Constructs the `Stats` of the `pomos`, bucketed in the timezone
+    /// of the `account`.
+    pub fn for_account(pomos: &'a [Pomo],
+                       account: &Account)
+                       -> Result<Stats<'a, chrono_tz::Tz>, Error> {
+        Ok(Stats::new(pomos, account.tz()?))
+    }
+}
+
+impl<'a, Tz: TimeZone> Stats<'a, Tz> {
+    /// Constructs the `Stats` of the `pomos`, bucketed in `tz`.
+    pub fn new(pomos: &'a [Pomo], tz: Tz) -> Stats<'a, Tz> {
+        Stats {
+            pomos: pomos,
+            tz: tz,
+        }
+    }
+
+    /// The number of `Pomo`s, abandoned ones included.
+    pub fn count(&self) -> usize {
+        self.pomos.len()
+    }
+
+    /// The number of finished `Pomo`s.
+    pub fn finished(&self) -> usize {
+        self.finished_pomos().count()
+    }
+
+    /// The number of abandoned `Pomo`s.
+    pub fn abandoned(&self) -> usize {
+        self.count() - self.finished()
+    }
+
+    /// The share of abandoned `Pomo`s, between `0.0` and `1.0`,
+    /// or `None` if there are no `Pomo`s at all.
+    pub fn abandonment_rate(&self) -> Option<f64> {
+        if self.pomos.is_empty() {
+            None
+        } else {
+            Some(self.abandoned() as f64 / self.count() as f64)
+        }
+    }
+
+    /// The total length of the finished `Pomo`s.
+    pub fn focus(&self) -> Duration {
+        self.finished_pomos().fold(Duration::zero(), |focus, pomo| focus + span(pomo))
+    }
+
+    /// The average length of the finished `Pomo`s, if any.
+    pub fn average_length(&self) -> Option<Duration> {
+        match self.finished() {
+            0 => None,
+            n => Some(self.focus() / n as i32),
+        }
+    }
+
+    /// The finished `Pomo`s of each local day.
+    pub fn per_day(&self) -> BTreeMap<NaiveDate, Bucket> {
+        self.bucket(|date| date)
+    }
+
+    /// The finished `Pomo`s of each local week, keyed by its Monday.
+    pub fn per_week(&self) -> BTreeMap<NaiveDate, Bucket> {
+        self.bucket(|date| date - Duration::days(date.weekday().num_days_from_monday() as i64))
+    }
+
+    /// The finished `Pomo`s of each local month, keyed by its first day.
+    pub fn per_month(&self) -> BTreeMap<NaiveDate, Bucket> {
+        self.bucket(|date| NaiveDate::from_ymd(date.year(), date.month(), 1))
+    }
+
+    /// The most consecutive local days with at least one finished `Pomo`.
+    pub fn longest_streak(&self) -> u32 {
+        let days = self.finished_pomos()
+            .map(|pomo| self.local(pomo).date())
+            .collect::<BTreeSet<_>>();
+
+        let mut longest = 0;
+        let mut streak = 0;
+        let mut last: Option<NaiveDate> = None;
+        for day in days {
+            streak = match last {
+                Some(last) if day - last == Duration::days(1) => streak + 1,
+                _ => 1,
+            };
+            longest = ::std::cmp::max(longest, streak);
+            last = Some(day);
+        }
+
+        longest
+    }
+
+    /// The number of finished `Pomo`s started in each local hour of the day.
+    pub fn per_hour(&self) -> [usize; 24] {
+        let mut hours = [0; 24];
+        for pomo in self.finished_pomos() {
+            hours[self.local(pomo).hour() as usize] += 1;
+        }
+        hours
+    }
+
+    /// The number of finished `Pomo`s started on each local weekday,
+    /// from Monday to Sunday.
+    pub fn per_weekday(&self) -> [usize; 7] {
+        let mut weekdays = [0; 7];
+        for pomo in self.finished_pomos() {
+            weekdays[self.local(pomo).weekday().num_days_from_monday() as usize] += 1;
+        }
+        weekdays
+    }
+
+    fn finished_pomos<'b>(&'b self) -> Box<Iterator<Item = &'a Pomo> + 'b> {
+        Box::new(self.pomos.iter().filter(|pomo| pomo.abandoned != Some(true)))
+    }
+
+    /// The wall-clock start of the `pomo`.
+    fn local(&self, pomo: &Pomo) -> NaiveDateTime {
+        pomo.local_start(&self.tz)
+    }
+
+    fn bucket<F>(&self, key: F) -> BTreeMap<NaiveDate, Bucket>
+        where F: Fn(NaiveDate) -> NaiveDate
+    {
+        let mut buckets = BTreeMap::new();
+        for pomo in self.finished_pomos() {
+            let bucket = buckets
+                .entry(key(self.local(pomo).date()))
+                .or_insert_with(Bucket::default);
+            bucket.count += 1;
+            bucket.focus = bucket.focus + span(pomo);
+        }
+        buckets
+    }
+}
+
+/// How long the `pomo` lasted.
+pub(crate) fn span(pomo: &Pomo) -> Duration {
+    pomo.ended_at.signed_duration_since(pomo.started_at)
+}
@@ -0,0 +1,419 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A local persistent mirror of `Pomo`, `Todo` and `SubTodo` records.
+//!
+//! The [`Store`](struct.Store.html) keeps every record in memory, keyed
+//! by `uuid` and indexed by `started_at` / `completed_at`, and persists
+//! them to an append-only file with one json record per line. Every change
+//! is appended as it is made, and [`compact`](struct.Store.html#method.compact)
+//! rewrites the file with only the current records.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! # use pomotodo::{Client, PomoParameter};
+//! # use pomotodo::store::Store;
+//! #
+//! # fn run() -> Result<(), pomotodo::errors::Error> {
+//! let client = Client::new("YOUR_ACCESS_TOKEN");
+//! let mut store = Store::open("pomotodo.jsonl")?;
+//!
+//! for pomo in client.pomos(PomoParameter::default())? {
+//!     store.put_pomo(pomo)?;
+//! }
+//!
+//! let pomos = store.pomos(&PomoParameter::default());
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, Bound};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use uuid::Uuid;
+use chrono::prelude::*;
+use serde_json;
+
+use errors::*;
+use fs::write_atomically;
+use pomo::{Pomo, PomoParameter};
+use todo::{Todo, SubTodo, TodoParameter};
+use validate::Violation;
+
+/// A local store of records, see the [module documentation](index.html).
+#[derive(Debug)]
+pub struct Store {
+    path: PathBuf,
+    file: File,
+    pomos: HashMap<Uuid, Pomo>,
+    todos: HashMap<Uuid, Todo>,
+    sub_todos: HashMap<Uuid, SubTodo>,
+    started: Index,
+    completed: Index,
+}
+
+/// The uuids of the records sharing a time.
+type Index = BTreeMap<DateTime<Utc>, BTreeSet<Uuid>>;
+
+/// A line of the file.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Record {
+    PutPomo { pomo: Pomo },
+    RemovePomo { uuid: Uuid },
+
+    /// The `sub_todos` of a `Todo` are not serialized with it.
+    PutTodo {
+        todo: Todo,
+        sub_todos: Option<Vec<Uuid>>,
+    },
+    RemoveTodo { uuid: Uuid },
+
+    PutSubTodo { sub_todo: SubTodo },
+    RemoveSubTodo { uuid: Uuid },
+}
+
+impl Store {
+    /// Opens the store at `path`, creating the file if it does not exist.
+    ///
+    /// A half written last line, left by a crash, is ignored.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Store, Error> {
+        let path = path.as_ref().to_path_buf();
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(&path)?;
+
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+
+        let mut store = Store {
+            path: path,
+            file: file,
+            pomos: HashMap::new(),
+            todos: HashMap::new(),
+            sub_todos: HashMap::new(),
+            started: Index::new(),
+            completed: Index::new(),
+        };
+
+        let complete = content.is_empty() || content.ends_with('\n');
+        let lines = content.lines().collect::<Vec<_>>();
+        for (i, line) in lines.iter().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            match serde_json::from_str(line) {
+                Ok(record) => store.apply(record),
+                Err(_) if i + 1 == lines.len() && !complete => {}
+                Err(e) => {
+                    return Err(e).chain_err(|| {
+                                                format!("corrupted line {} of {}",
+                                                        i + 1,
+                                                        store.path.display())
+                                            })
+                }
+            }
+        }
+
+        if !complete {
+            store.file.write_all(b"\n")?;
+        }
+        Ok(store)
+    }
+
+    /// The path of the file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The `Pomo` specified by `uuid`.
+    pub fn pomo(&self, uuid: Uuid) -> Option<&Pomo> {
+        self.pomos.get(&uuid)
+    }
+
+    /// All `Pomo` that matched the `param`, the latest first.
+    pub fn pomos(&self, param: &PomoParameter) -> Vec<&Pomo> {
+        let (later, earlier) = param.started_bounds();
+        let uuids = match range(&self.started, later, earlier) {
+            Some(uuids) => uuids,
+            None => return Vec::new(),
+        };
+
+        uuids
+            .filter_map(|uuid| self.pomos.get(uuid))
+            .filter(|pomo| param.matches(pomo))
+            .collect()
+    }
+
+    /// Inserts or replaces a `Pomo`, which must have its `uuid`.
+    pub fn put_pomo(&mut self, pomo: Pomo) -> Result<(), Error> {
+        if pomo.uuid.is_none() {
+            return Err(ErrorKind::Invalid(vec![Violation::Required("uuid")]).into());
+        }
+        self.append(Record::PutPomo { pomo: pomo })
+    }
+
+    /// Removes the `Pomo` specified by `uuid`.
+    pub fn remove_pomo(&mut self, uuid: Uuid) -> Result<Option<Pomo>, Error> {
+        let pomo = self.pomos.get(&uuid).cloned();
+        if pomo.is_some() {
+            self.append(Record::RemovePomo { uuid: uuid })?;
+        }
+        Ok(pomo)
+    }
+
+    /// The `Todo` specified by `uuid`, its `sub_todos` list every stored
+    /// `SubTodo` it owns.
+    pub fn todo(&self, uuid: Uuid) -> Option<&Todo> {
+        self.todos.get(&uuid)
+    }
+
+    /// All `Todo` that matched the `param`, the latest completed first
+    /// and then the latest created.
+    pub fn todos(&self, param: &TodoParameter) -> Vec<&Todo> {
+        let (later, earlier) = param.completed_bounds();
+        if later.is_some() || earlier.is_some() {
+            return match range(&self.completed, later, earlier) {
+                Some(uuids) => {
+                    uuids
+                        .filter_map(|uuid| self.todos.get(uuid))
+                        .filter(|todo| param.matches(todo))
+                        .collect()
+                }
+                None => Vec::new(),
+            };
+        }
+
+        let mut todos = self.todos.values().filter(|todo| param.matches(todo)).collect::<Vec<_>>();
+        todos.sort_by(|a, b| (b.completed_at, b.created_at).cmp(&(a.completed_at, a.created_at)));
+        todos
+    }
+
+    /// Inserts or replaces a `Todo`, which must have its `uuid`.
+    pub fn put_todo(&mut self, todo: Todo) -> Result<(), Error> {
+        if todo.uuid.is_none() {
+            return Err(ErrorKind::Invalid(vec![Violation::Required("uuid")]).into());
+        }
+        self.append(Record::PutTodo {
+                        sub_todos: todo.sub_todos.clone(),
+                        todo: todo,
+                    })
+    }
+
+    /// Removes the `Todo` specified by `uuid` with all its `SubTodo`s.
+    pub fn remove_todo(&mut self, uuid: Uuid) -> Result<Option<Todo>, Error> {
+        let todo = self.todos.get(&uuid).cloned();
+        if todo.is_some() {
+            self.append(Record::RemoveTodo { uuid: uuid })?;
+        }
+        Ok(todo)
+    }
+
+    /// The `SubTodo` specified by `uuid`.
+    pub fn sub_todo(&self, uuid: Uuid) -> Option<&SubTodo> {
+        self.sub_todos.get(&uuid)
+    }
+
+    /// All `SubTodo` owned by `parent`, the earliest created first.
+    pub fn sub_todos(&self, parent: Uuid) -> Vec<&SubTodo> {
+        let mut sub_todos = self.sub_todos
+            .values()
+            .filter(|sub_todo| sub_todo.parent_uuid == Some(parent))
+            .collect::<Vec<_>>();
+        sub_todos.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+        sub_todos
+    }
+
+    /// Inserts or replaces a `SubTodo` owned by `parent`,
+    /// which must have its `uuid`.
+    pub fn put_sub_todo(&mut self, parent: Uuid, mut sub_todo: SubTodo) -> Result<(), Error> {
+        if sub_todo.uuid.is_none() {
+            return Err(ErrorKind::Invalid(vec![Violation::Required("uuid")]).into());
+        }
+        sub_todo.parent_uuid = Some(parent);
+        self.append(Record::PutSubTodo { sub_todo: sub_todo })
+    }
+
+    /// Removes the `SubTodo` specified by `uuid`.
+    pub fn remove_sub_todo(&mut self, uuid: Uuid) -> Result<Option<SubTodo>, Error> {
+        let sub_todo = self.sub_todos.get(&uuid).cloned();
+        if sub_todo.is_some() {
+            self.append(Record::RemoveSubTodo { uuid: uuid })?;
+        }
+        Ok(sub_todo)
+    }
+
+    /// Rewrites the file with only the current records.
+    pub fn compact(&mut self) -> Result<(), Error> {
+        write_atomically(&self.path, |file| {
+            for pomo in self.pomos.values() {
+                write_record(file, &Record::PutPomo { pomo: pomo.clone() })?;
+            }
+            for todo in self.todos.values() {
+                write_record(file,
+                             &Record::PutTodo {
+                                  todo: todo.clone(),
+                                  sub_todos: todo.sub_todos.clone(),
+                              })?;
+            }
+            for sub_todo in self.sub_todos.values() {
+                write_record(file, &Record::PutSubTodo { sub_todo: sub_todo.clone() })?;
+            }
+            Ok(())
+        })?;
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        Ok(())
+    }
+
+    /// Persists the `record`, then applies it in memory.
+    fn append(&mut self, record: Record) -> Result<(), Error> {
+        write_record(&mut self.file, &record)?;
+        self.apply(record);
+        Ok(())
+    }
+
+    fn apply(&mut self, record: Record) {
+        match record {
+            Record::PutPomo { pomo } => {
+                if let Some(uuid) = pomo.uuid {
+                    self.take_pomo(uuid);
+                    index(&mut self.started, pomo.started_at, uuid);
+                    self.pomos.insert(uuid, pomo);
+                }
+            }
+            Record::RemovePomo { uuid } => {
+                self.take_pomo(uuid);
+            }
+            Record::PutTodo { mut todo, sub_todos } => {
+                if let Some(uuid) = todo.uuid {
+                    self.take_todo(uuid);
+                    if let Some(at) = todo.completed_at {
+                        index(&mut self.completed, at, uuid);
+                    }
+                    todo.sub_todos = sub_todos;
+                    self.todos.insert(uuid, todo);
+
+                    let stored = self.sub_todos
+                        .values()
+                        .filter(|sub_todo| sub_todo.parent_uuid == Some(uuid))
+                        .filter_map(|sub_todo| sub_todo.uuid)
+                        .collect::<Vec<_>>();
+                    for sub_todo in stored {
+                        self.link(Some(uuid), sub_todo);
+                    }
+                }
+            }
+            Record::RemoveTodo { uuid } => {
+                self.take_todo(uuid);
+                self.sub_todos.retain(|_, sub_todo| sub_todo.parent_uuid != Some(uuid));
+            }
+            Record::PutSubTodo { sub_todo } => {
+                if let Some(uuid) = sub_todo.uuid {
+                    let parent = sub_todo.parent_uuid;
+                    if let Some(old) = self.sub_todos.insert(uuid, sub_todo) {
+                        self.unlink(old.parent_uuid, uuid);
+                    }
+                    self.link(parent, uuid);
+                }
+            }
+            Record::RemoveSubTodo { uuid } => {
+                if let Some(old) = self.sub_todos.remove(&uuid) {
+                    self.unlink(old.parent_uuid, uuid);
+                }
+            }
+        }
+    }
+
+    fn take_pomo(&mut self, uuid: Uuid) -> Option<Pomo> {
+        let pomo = self.pomos.remove(&uuid);
+        if let Some(ref pomo) = pomo {
+            unindex(&mut self.started, pomo.started_at, uuid);
+        }
+        pomo
+    }
+
+    /// Adds `uuid` to the `sub_todos` of the `Todo` specified by `parent`.
+    fn link(&mut self, parent: Option<Uuid>, uuid: Uuid) {
+        if let Some(todo) = parent.and_then(|parent| self.todos.get_mut(&parent)) {
+            let sub_todos = todo.sub_todos.get_or_insert_with(Vec::new);
+            if !sub_todos.contains(&uuid) {
+                sub_todos.push(uuid);
+            }
+        }
+    }
+
+    /// Removes `uuid` from the `sub_todos` of the `Todo` specified by `parent`.
+    fn unlink(&mut self, parent: Option<Uuid>, uuid: Uuid) {
+        if let Some(todo) = parent.and_then(|parent| self.todos.get_mut(&parent)) {
+            if let Some(ref mut sub_todos) = todo.sub_todos {
+                sub_todos.retain(|sub_todo| *sub_todo != uuid);
+            }
+        }
+    }
+
+    fn take_todo(&mut self, uuid: Uuid) -> Option<Todo> {
+        let todo = self.todos.remove(&uuid);
+        if let Some(at) = todo.as_ref().and_then(|todo| todo.completed_at) {
+            unindex(&mut self.completed, at, uuid);
+        }
+        todo
+    }
+}
+
+fn write_record<W: Write>(out: &mut W, record: &Record) -> Result<(), Error> {
+    let mut line = serde_json::to_vec(record)?;
+    line.push(b'\n');
+    out.write_all(&line)?;
+    out.flush()?;
+    Ok(())
+}
+
+fn index(index: &mut Index, at: DateTime<Utc>, uuid: Uuid) {
+    index.entry(at).or_insert_with(BTreeSet::new).insert(uuid);
+}
+
+fn unindex(index: &mut Index, at: DateTime<Utc>, uuid: Uuid) {
+    let empty = match index.get_mut(&at) {
+        Some(uuids) => {
+            uuids.remove(&uuid);
+            uuids.is_empty()
+        }
+        None => false,
+    };
+
+    if empty {
+        index.remove(&at);
+    }
+}
+
+/// The uuids indexed strictly between `later` and `earlier`, the latest
+/// first, `None` if the range is empty.
+fn range<'a>(index: &'a Index,
+             later: Option<DateTime<Utc>>,
+             earlier: Option<DateTime<Utc>>)
+             -> Option<Box<Iterator<Item = &'a Uuid> + 'a>> {
+    if let (Some(later), Some(earlier)) = (later, earlier) {
+        if later >= earlier {
+            return None;
+        }
+    }
+
+    let bound = |at: Option<DateTime<Utc>>| at.map_or(Bound::Unbounded, Bound::Excluded);
+    let uuids = index
+        .range((bound(later), bound(earlier)))
+        .rev()
+        .flat_map(|(_, uuids)| uuids.iter());
+    Some(Box::new(uuids))
+}
@@ -0,0 +1,876 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Two-way synchronization between a local [`Store`](../store/struct.Store.html)
+//! and Pomotodo server.
+//!
+//! The [`SyncEngine`](struct.SyncEngine.html) records every local change as
+//! a pending operation, and [`sync`](struct.SyncEngine.html#method.sync)
+//! first pulls the records changed on server since the last
+//! [`SyncToken`](struct.SyncToken.html), then pushes the pending operations
+//! in order.
+//!
+//! Server can not be asked for the records updated since a time, so the pull
+//! relies on what can be filtered: the `Pomo`s started since the latest one
+//! seen, every uncompleted `Todo` and the `Todo`s completed since the latest
+//! one seen. Only the records with a newer `updated_at` than the local copy
+//! are stored, together with the `SubTodo`s of the changed `Todo`s. An
+//! uncompleted `Todo` missing from server's page is looked up on its own,
+//! and removed locally only if server no longer has it.
+//!
+//! A pending edit of a `Todo` or `SubTodo` conflicts with server if its
+//! `updated_at` changed since the local edit began, and the conflict is
+//! settled by the [`Strategy`](enum.Strategy.html) of the engine.
+//!
+//! # Example
+//!
+//! ```rust,no_run
+//! # use pomotodo::Client;
+//! # use pomotodo::sync::{SyncEngine, Strategy};
+//! #
+//! # fn run() -> Result<(), pomotodo::errors::Error> {
+//! let client = Client::new("YOUR_ACCESS_TOKEN");
+//! let mut engine = SyncEngine::open("pomotodo.jsonl")?;
+//! engine.with_strategy(Strategy::ServerWins);
+//!
+//! let report = engine.sync(&client)?;
+//! println!("pulled {}, pushed {}", report.pulled, report.pushed);
+//! # Ok(())
+//! # }
+//! ```
+
+use std::cmp;
+use std::collections::HashSet;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use uuid::Uuid;
+use chrono::prelude::*;
+use chrono::Duration;
+use serde_json;
+
+use errors::*;
+use client::{Client, is_not_found};
+use store::Store;
+use fs::write_atomically;
+use patch::{Field, PomoPatch, TodoPatch, SubTodoPatch};
+use validate::ensure_valid;
+use pomo::{Pomo, PomoParameter};
+use todo::{Todo, SubTodo, TodoParameter};
+
+/// Pulls and pushes the changes of a local [`Store`](../store/struct.Store.html),
+/// see the [module documentation](index.html).
+///
+/// The store is kept at the opened path, and the sync token with the
+/// pending operations next to it, with a `.sync` suffix.
+#[derive(Debug)]
+pub struct SyncEngine {
+    store: Store,
+    state_path: PathBuf,
+    state: State,
+    strategy: Strategy,
+}
+
+/// How far the local store is synchronized with server.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SyncToken {
+    /// The latest `started_at` of the pulled `Pomo`s. The next pull starts
+    /// a second earlier, for the `Pomo`s sharing that time.
+    pub pomos: Option<DateTime<Utc>>,
+
+    /// The latest `completed_at` of the pulled `Todo`s, pulled from a
+    /// second earlier the same way.
+    pub todos: Option<DateTime<Utc>>,
+
+    /// When the last sync finished.
+    pub synced_at: Option<DateTime<Utc>>,
+}
+
+/// How a [`Conflict`](enum.Conflict.html) is settled.
+pub enum Strategy {
+    /// Drop the local edit.
+    ServerWins,
+
+    /// Overwrite server with the local record.
+    LocalWins,
+
+    /// Take every field changed on one side only, and the server value
+    /// of a field changed on both sides. The default.
+    Merge,
+
+    /// Let the callback decide for each conflict.
+    Callback(Box<Fn(&Conflict) -> Resolution>),
+}
+
+/// A record edited both locally and on server since the local edit began.
+#[derive(Debug, Clone)]
+pub enum Conflict {
+    Todo {
+        base: Todo,
+        local: Todo,
+        server: Todo,
+    },
+    SubTodo {
+        base: SubTodo,
+        local: SubTodo,
+        server: SubTodo,
+    },
+}
+
+/// The side taken for a [`Conflict`](enum.Conflict.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    Server,
+    Local,
+    Merge,
+}
+
+/// What a [`sync`](struct.SyncEngine.html#method.sync) did.
+#[derive(Debug, Default)]
+pub struct SyncReport {
+    /// The records stored from server.
+    pub pulled: usize,
+
+    /// The pending operations sent to server.
+    pub pushed: usize,
+
+    /// The records removed locally since they are gone on server.
+    pub removed: usize,
+
+    /// The conflicts met and how they were settled.
+    pub conflicts: Vec<(Conflict, Resolution)>,
+}
+
+/// The persisted part of the engine.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct State {
+    token: SyncToken,
+    pending: Vec<Pending>,
+}
+
+/// A local change waiting to be pushed. The records themselves are in the
+/// store, locally created ones under a random `uuid` until pushed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Pending {
+    CreatePomo { uuid: Uuid },
+    UpdatePomo { uuid: Uuid, base: Pomo },
+    DeletePomo { uuid: Uuid },
+    CreateTodo { uuid: Uuid },
+    UpdateTodo { uuid: Uuid, base: Todo },
+    DeleteTodo { uuid: Uuid },
+    CreateSubTodo { parent: Uuid, uuid: Uuid },
+    UpdateSubTodo {
+        parent: Uuid,
+        uuid: Uuid,
+        base: SubTodo,
+    },
+    DeleteSubTodo { parent: Uuid, uuid: Uuid },
+}
+
+impl Default for Strategy {
+    fn default() -> Strategy {
+        Strategy::Merge
+    }
+}
+
+impl ::std::fmt::Debug for Strategy {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Strategy::ServerWins => write!(f, "ServerWins"),
+            Strategy::LocalWins => write!(f, "LocalWins"),
+            Strategy::Merge => write!(f, "Merge"),
+            Strategy::Callback(_) => write!(f, "Callback(..)"),
+        }
+    }
+}
+
+impl Strategy {
+    fn resolve(&self, conflict: &Conflict) -> Resolution {
+        match *self {
+            Strategy::ServerWins => Resolution::Server,
+            Strategy::LocalWins => Resolution::Local,
+            Strategy::Merge => Resolution::Merge,
+            Strategy::Callback(ref callback) => callback(conflict),
+        }
+    }
+}
+
+impl SyncEngine {
+    /// Opens the store at `path` with its sync state.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<SyncEngine, Error> {
+        let store = Store::open(&path)?;
+
+        let mut state_path = path.as_ref().to_path_buf().into_os_string();
+        state_path.push(".sync");
+        let state_path = PathBuf::from(state_path);
+
+        let state = if state_path.exists() {
+            serde_json::from_reader(File::open(&state_path)?)?
+        } else {
+            State::default()
+        };
+
+        Ok(SyncEngine {
+               store: store,
+               state_path: state_path,
+               state: state,
+               strategy: Strategy::default(),
+           })
+    }
+
+    /// Set the [`Strategy`](enum.Strategy.html) settling the conflicts.
+    pub fn with_strategy(&mut self, strategy: Strategy) -> &mut SyncEngine {
+        self.strategy = strategy;
+        self
+    }
+
+    /// The local records, including the pending changes.
+    pub fn store(&self) -> &Store {
+        &self.store
+    }
+
+    /// How far the store is synchronized.
+    pub fn token(&self) -> &SyncToken {
+        &self.state.token
+    }
+
+    /// The number of local changes waiting to be pushed.
+    pub fn pending(&self) -> usize {
+        self.state.pending.len()
+    }
+
+    /// Records a new `Pomo`, returning its local `uuid`.
+    pub fn create_pomo(&mut self, mut pomo: Pomo) -> Result<Uuid, Error> {
+        ensure_valid(&pomo)?;
+
+        let uuid = Uuid::new_v4();
+        pomo.uuid = Some(uuid);
+        self.store.put_pomo(pomo)?;
+        self.push_pending(Pending::CreatePomo { uuid: uuid })?;
+        Ok(uuid)
+    }
+
+    /// Edits the `Pomo` specified by `uuid`, only the `description`
+    /// is pushed to server.
+    pub fn edit_pomo<F>(&mut self, uuid: Uuid, edit: F) -> Result<(), Error>
+        where F: FnOnce(&mut Pomo)
+    {
+        let base = self.store.pomo(uuid).cloned().ok_or(ErrorKind::NotFound(uuid))?;
+        let mut pomo = base.clone();
+        edit(&mut pomo);
+        pomo.uuid = Some(uuid);
+        ensure_valid(&pomo_patch(&base, &pomo))?;
+
+        if !self.is_pending(uuid) {
+            self.state.pending.push(Pending::UpdatePomo {
+                                        uuid: uuid,
+                                        base: base,
+                                    });
+        }
+        self.store.put_pomo(pomo)?;
+        self.save()
+    }
+
+    /// Deletes the `Pomo` specified by `uuid`.
+    pub fn delete_pomo(&mut self, uuid: Uuid) -> Result<(), Error> {
+        self.delete(uuid, Pending::DeletePomo { uuid: uuid });
+        self.store.remove_pomo(uuid)?;
+        self.save()
+    }
+
+    /// Records a new `Todo`, returning its local `uuid`.
+    pub fn create_todo(&mut self, mut todo: Todo) -> Result<Uuid, Error> {
+        ensure_valid(&todo)?;
+
+        let uuid = Uuid::new_v4();
+        todo.uuid = Some(uuid);
+        self.store.put_todo(todo)?;
+        self.push_pending(Pending::CreateTodo { uuid: uuid })?;
+        Ok(uuid)
+    }
+
+    /// Edits the `Todo` specified by `uuid`.
+    pub fn edit_todo<F>(&mut self, uuid: Uuid, edit: F) -> Result<(), Error>
+        where F: FnOnce(&mut Todo)
+    {
+        let base = self.store.todo(uuid).cloned().ok_or(ErrorKind::NotFound(uuid))?;
+        let mut todo = base.clone();
+        edit(&mut todo);
+        todo.uuid = Some(uuid);
+        ensure_valid(&todo_patch(&base, &todo))?;
+
+        if !self.is_pending(uuid) {
+            self.state.pending.push(Pending::UpdateTodo {
+                                        uuid: uuid,
+                                        base: base,
+                                    });
+        }
+        self.store.put_todo(todo)?;
+        self.save()
+    }
+
+    /// Deletes the `Todo` specified by `uuid` with all its `SubTodo`s.
+    pub fn delete_todo(&mut self, uuid: Uuid) -> Result<(), Error> {
+        self.delete(uuid, Pending::DeleteTodo { uuid: uuid });
+        self.store.remove_todo(uuid)?;
+        self.save()
+    }
+
+    /// Records a new `SubTodo` under `parent`, returning its local `uuid`.
+    pub fn create_sub_todo(&mut self, parent: Uuid, mut sub_todo: SubTodo) -> Result<Uuid, Error> {
+        ensure_valid(&sub_todo)?;
+        if self.store.todo(parent).is_none() {
+            return Err(ErrorKind::NotFound(parent).into());
+        }
+
+        let uuid = Uuid::new_v4();
+        sub_todo.uuid = Some(uuid);
+        self.store.put_sub_todo(parent, sub_todo)?;
+        self.push_pending(Pending::CreateSubTodo {
+                              parent: parent,
+                              uuid: uuid,
+                          })?;
+        Ok(uuid)
+    }
+
+    /// Edits the `SubTodo` specified by `uuid`.
+    pub fn edit_sub_todo<F>(&mut self, uuid: Uuid, edit: F) -> Result<(), Error>
+        where F: FnOnce(&mut SubTodo)
+    {
+        let base = self.store.sub_todo(uuid).cloned().ok_or(ErrorKind::NotFound(uuid))?;
+        let parent = base.parent_uuid.ok_or(ErrorKind::NotFound(uuid))?;
+        let mut sub_todo = base.clone();
+        edit(&mut sub_todo);
+        sub_todo.uuid = Some(uuid);
+        ensure_valid(&sub_todo_patch(&base, &sub_todo))?;
+
+        if !self.is_pending(uuid) {
+            self.state.pending.push(Pending::UpdateSubTodo {
+                                        parent: parent,
+                                        uuid: uuid,
+                                        base: base,
+                                    });
+        }
+        self.store.put_sub_todo(parent, sub_todo)?;
+        self.save()
+    }
+
+    /// Deletes the `SubTodo` specified by `uuid`.
+    pub fn delete_sub_todo(&mut self, uuid: Uuid) -> Result<(), Error> {
+        let parent = self.store
+            .sub_todo(uuid)
+            .and_then(|sub_todo| sub_todo.parent_uuid)
+            .ok_or(ErrorKind::NotFound(uuid))?;
+
+        self.delete(uuid,
+                    Pending::DeleteSubTodo {
+                        parent: parent,
+                        uuid: uuid,
+                    });
+        self.store.remove_sub_todo(uuid)?;
+        self.save()
+    }
+
+    /// Pulls the changes from server, then pushes the pending ones.
+    ///
+    /// The pending operations are pushed in order and each one is forgotten
+    /// as soon as it succeeded, so a failed sync can simply be run again.
+    pub fn sync(&mut self, client: &Client) -> Result<SyncReport, Error> {
+        let mut report = SyncReport::default();
+
+        self.pull_pomos(client, &mut report)?;
+        self.pull_todos(client, &mut report)?;
+        self.save()?;
+
+        while !self.state.pending.is_empty() {
+            let pending = self.state.pending[0].clone();
+            self.push(client, pending, &mut report)?;
+            self.state.pending.remove(0);
+            report.pushed += 1;
+            self.save()?;
+        }
+
+        self.state.token.synced_at = Some(Utc::now());
+        self.save()?;
+        Ok(report)
+    }
+
+    fn pull_pomos(&mut self, client: &Client, report: &mut SyncReport) -> Result<(), Error> {
+        let since = self.state.token.pomos;
+        let mut latest = since;
+
+        for &abandoned in &[false, true] {
+            for &manual in &[false, true] {
+                let mut param = PomoParameter::default();
+                param.with_abandoned(abandoned).with_manual(manual);
+                if let Some(since) = since {
+                    param.with_started_later(since - Duration::seconds(1));
+                }
+
+                for pomo in client.pomos_iter(param) {
+                    let pomo = pomo?;
+                    latest = cmp::max(latest, Some(pomo.started_at));
+
+                    let uuid = match pomo.uuid {
+                        Some(uuid) => uuid,
+                        None => continue,
+                    };
+                    let local = self.store.pomo(uuid).map(|pomo| pomo.updated_at);
+                    if !self.is_pending(uuid) && is_newer(local, pomo.updated_at) {
+                        self.store.put_pomo(pomo)?;
+                        report.pulled += 1;
+                    }
+                }
+            }
+        }
+
+        self.state.token.pomos = latest;
+        Ok(())
+    }
+
+    fn pull_todos(&mut self, client: &Client, report: &mut SyncReport) -> Result<(), Error> {
+        let mut uncompleted = TodoParameter::default();
+        uncompleted.with_completed(false);
+        let mut todos = client.todos(uncompleted.clone())?;
+
+        let mut param = TodoParameter::default();
+        param.with_completed(true);
+        if let Some(since) = self.state.token.todos {
+            param.with_completed_later(since - Duration::seconds(1));
+        }
+        for todo in client.todos_iter(param) {
+            todos.push(todo?);
+        }
+
+        let seen = todos.iter().filter_map(|todo| todo.uuid).collect::<HashSet<_>>();
+        let gone = self.store
+            .todos(&uncompleted)
+            .iter()
+            .filter_map(|todo| todo.uuid)
+            .filter(|uuid| !seen.contains(uuid))
+            .collect::<Vec<_>>();
+        // The uncompleted todos come in a single page, so one missing from it
+        // may still be on server, past the page.
+        for uuid in gone {
+            if self.is_pending(uuid) {
+                continue;
+            }
+            match client.todo(uuid) {
+                Ok(todo) => todos.push(todo),
+                Err(ref e) if is_not_found(e) => {
+                    self.store.remove_todo(uuid)?;
+                    report.removed += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        for todo in todos {
+            self.state.token.todos = cmp::max(self.state.token.todos, todo.completed_at);
+
+            let uuid = match todo.uuid {
+                Some(uuid) => uuid,
+                None => continue,
+            };
+            let local = self.store.todo(uuid).map(|todo| todo.updated_at);
+            if self.is_pending(uuid) || !is_newer(local, todo.updated_at) {
+                continue;
+            }
+
+            self.store.put_todo(todo)?;
+            report.pulled += 1;
+            self.pull_sub_todos(client, uuid, report)?;
+        }
+
+        Ok(())
+    }
+
+    fn pull_sub_todos(&mut self,
+                      client: &Client,
+                      parent: Uuid,
+                      report: &mut SyncReport)
+                      -> Result<(), Error> {
+        let sub_todos = client.subtodos(parent)?;
+
+        let seen = sub_todos.iter().filter_map(|sub_todo| sub_todo.uuid).collect::<HashSet<_>>();
+        let gone = self.store
+            .sub_todos(parent)
+            .iter()
+            .filter_map(|sub_todo| sub_todo.uuid)
+            .filter(|uuid| !seen.contains(uuid))
+            .collect::<Vec<_>>();
+        for uuid in gone {
+            if !self.is_pending(uuid) {
+                self.store.remove_sub_todo(uuid)?;
+                report.removed += 1;
+            }
+        }
+
+        for sub_todo in sub_todos {
+            let uuid = match sub_todo.uuid {
+                Some(uuid) => uuid,
+                None => continue,
+            };
+            let local = self.store.sub_todo(uuid).map(|sub_todo| sub_todo.updated_at);
+            if !self.is_pending(uuid) && is_newer(local, sub_todo.updated_at) {
+                self.store.put_sub_todo(parent, sub_todo)?;
+                report.pulled += 1;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn push(&mut self, client: &Client, pending: Pending, report: &mut SyncReport)
+            -> Result<(), Error> {
+        match pending {
+            Pending::CreatePomo { uuid } => {
+                if let Some(pomo) = self.store.pomo(uuid).cloned() {
+                    let created = client.submit_pomo(&Pomo {
+                                                         uuid: None,
+                                                         created_at: None,
+                                                         updated_at: None,
+                                                         ..pomo
+                                                     })?;
+                    self.store.remove_pomo(uuid)?;
+                    self.store.put_pomo(created)?;
+                }
+            }
+            Pending::UpdatePomo { uuid, base } => {
+                if let Some(pomo) = self.store.pomo(uuid).cloned() {
+                    let patch = pomo_patch(&base, &pomo);
+                    if !patch.is_empty() {
+                        match client.update_pomo(uuid, &patch) {
+                            Ok(updated) => self.store.put_pomo(updated)?,
+                            Err(ref e) if is_not_found(e) => {
+                                self.store.remove_pomo(uuid)?;
+                            }
+                            Err(e) => return Err(e),
+                        }
+                    }
+                }
+            }
+            Pending::DeletePomo { uuid } => ignore_not_found(client.delete_pomo(uuid))?,
+            Pending::CreateTodo { uuid } => {
+                if let Some(todo) = self.store.todo(uuid).cloned() {
+                    let created = client.create_todo(&Todo {
+                                                          uuid: None,
+                                                          created_at: None,
+                                                          updated_at: None,
+                                                          sub_todos: None,
+                                                          ..todo
+                                                      })?;
+                    self.replace_todo(uuid, created)?;
+                }
+            }
+            Pending::UpdateTodo { uuid, base } => {
+                let local = match self.store.todo(uuid).cloned() {
+                    Some(local) => local,
+                    None => return Ok(()),
+                };
+                let server = match client.todo(uuid) {
+                    Ok(server) => server,
+                    Err(ref e) if is_not_found(e) => {
+                        self.store.remove_todo(uuid)?;
+                        return Ok(());
+                    }
+                    Err(e) => return Err(e),
+                };
+
+                let target = if server.updated_at == base.updated_at {
+                    local
+                } else {
+                    let conflict = Conflict::Todo {
+                        base: base,
+                        local: local,
+                        server: server.clone(),
+                    };
+                    let resolution = self.strategy.resolve(&conflict);
+                    let target = match (resolution, &conflict) {
+                        (Resolution::Server, _) => server.clone(),
+                        (Resolution::Local, &Conflict::Todo { ref local, .. }) => local.clone(),
+                        (Resolution::Merge, &Conflict::Todo { ref base, ref local, .. }) => {
+                            merge_todo(base, local, &server)
+                        }
+                        _ => unreachable!(),
+                    };
+                    report.conflicts.push((conflict, resolution));
+                    target
+                };
+
+                let patch = todo_patch(&server, &target);
+                let updated = if patch.is_empty() {
+                    server
+                } else {
+                    client.update_todo(uuid, &patch)?
+                };
+                self.store.put_todo(updated)?;
+            }
+            Pending::DeleteTodo { uuid } => ignore_not_found(client.delete_todo(uuid))?,
+            Pending::CreateSubTodo { parent, uuid } => {
+                if let Some(sub_todo) = self.store.sub_todo(uuid).cloned() {
+                    let created = client.create_subtodo(parent,
+                                                        &SubTodo {
+                                                             uuid: None,
+                                                             parent_uuid: None,
+                                                             created_at: None,
+                                                             updated_at: None,
+                                                             ..sub_todo
+                                                         })?;
+                    self.store.remove_sub_todo(uuid)?;
+                    self.store.put_sub_todo(parent, created)?;
+                }
+            }
+            Pending::UpdateSubTodo { parent, uuid, base } => {
+                let local = match self.store.sub_todo(uuid).cloned() {
+                    Some(local) => local,
+                    None => return Ok(()),
+                };
+                let server = match client.subtodo(parent, uuid) {
+                    Ok(server) => server,
+                    Err(ref e) if is_not_found(e) => {
+                        self.store.remove_sub_todo(uuid)?;
+                        return Ok(());
+                    }
+                    Err(e) => return Err(e),
+                };
+
+                let target = if server.updated_at == base.updated_at {
+                    local
+                } else {
+                    let conflict = Conflict::SubTodo {
+                        base: base,
+                        local: local,
+                        server: server.clone(),
+                    };
+                    let resolution = self.strategy.resolve(&conflict);
+                    let target = match (resolution, &conflict) {
+                        (Resolution::Server, _) => server.clone(),
+                        (Resolution::Local, &Conflict::SubTodo { ref local, .. }) => local.clone(),
+                        (Resolution::Merge, &Conflict::SubTodo { ref base, ref local, .. }) => {
+                            merge_sub_todo(base, local, &server)
+                        }
+                        _ => unreachable!(),
+                    };
+                    report.conflicts.push((conflict, resolution));
+                    target
+                };
+
+                let patch = sub_todo_patch(&server, &target);
+                let updated = if patch.is_empty() {
+                    server
+                } else {
+                    client.update_subtodo(parent, uuid, &patch)?
+                };
+                self.store.put_sub_todo(parent, updated)?;
+            }
+            Pending::DeleteSubTodo { parent, uuid } => {
+                ignore_not_found(client.delete_subtodo(parent, uuid))?
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the locally created `Todo` by the one created on server,
+    /// moving its `SubTodo`s and pending operations to the new `uuid`.
+    fn replace_todo(&mut self, local: Uuid, created: Todo) -> Result<(), Error> {
+        let uuid = match created.uuid {
+            Some(uuid) => uuid,
+            None => return Ok(()),
+        };
+        let sub_todos = self.store.sub_todos(local).into_iter().cloned().collect::<Vec<_>>();
+
+        self.store.remove_todo(local)?;
+        self.store.put_todo(created)?;
+        for sub_todo in sub_todos {
+            self.store.put_sub_todo(uuid, sub_todo)?;
+        }
+
+        for pending in &mut self.state.pending {
+            match *pending {
+                Pending::CreateSubTodo { ref mut parent, .. } |
+                Pending::UpdateSubTodo { ref mut parent, .. } |
+                Pending::DeleteSubTodo { ref mut parent, .. } => {
+                    if *parent == local {
+                        *parent = uuid;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Forgets the pending operations of the record `uuid` and its children,
+    /// then queues the `delete` unless the record was created locally.
+    fn delete(&mut self, uuid: Uuid, delete: Pending) {
+        let created = self.state
+            .pending
+            .iter()
+            .any(|pending| pending.is_create() && pending.uuid() == uuid);
+
+        self.state.pending.retain(|pending| !pending.concerns(uuid));
+        if !created {
+            self.state.pending.push(delete);
+        }
+    }
+
+    fn push_pending(&mut self, pending: Pending) -> Result<(), Error> {
+        self.state.pending.push(pending);
+        self.save()
+    }
+
+    fn is_pending(&self, uuid: Uuid) -> bool {
+        self.state.pending.iter().any(|pending| pending.uuid() == uuid)
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        write_atomically(&self.state_path, |file| Ok(serde_json::to_writer(file, &self.state)?))
+    }
+}
+
+impl Pending {
+    /// The `uuid` of the record.
+    fn uuid(&self) -> Uuid {
+        match *self {
+            Pending::CreatePomo { uuid } |
+            Pending::UpdatePomo { uuid, .. } |
+            Pending::DeletePomo { uuid } |
+            Pending::CreateTodo { uuid } |
+            Pending::UpdateTodo { uuid, .. } |
+            Pending::DeleteTodo { uuid } |
+            Pending::CreateSubTodo { uuid, .. } |
+            Pending::UpdateSubTodo { uuid, .. } |
+            Pending::DeleteSubTodo { uuid, .. } => uuid,
+        }
+    }
+
+    /// Whether the operation creates its record.
+    fn is_create(&self) -> bool {
+        match *self {
+            Pending::CreatePomo { .. } |
+            Pending::CreateTodo { .. } |
+            Pending::CreateSubTodo { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the operation is on the record `uuid` or one of its children.
+    fn concerns(&self, uuid: Uuid) -> bool {
+        match *self {
+            Pending::CreateSubTodo { parent, .. } |
+            Pending::UpdateSubTodo { parent, .. } |
+            Pending::DeleteSubTodo { parent, .. } if parent == uuid => true,
+            _ => self.uuid() == uuid,
+        }
+    }
+}
+
+/// Whether a record updated at `server` should replace the `local` copy,
+/// which is `None` if there is no local copy at all.
+fn is_newer(local: Option<Option<DateTime<Utc>>>, server: Option<DateTime<Utc>>) -> bool {
+    match local {
+        Some(local) => server > local,
+        None => true,
+    }
+}
+
+/// A record already deleted on server is fine to delete.
+fn ignore_not_found(result: Result<(), Error>) -> Result<(), Error> {
+    match result {
+        Err(ref e) if is_not_found(e) => Ok(()),
+        result => result,
+    }
+}
+
+/// The server value if it changed, otherwise the local one.
+fn pick<T: PartialEq + Clone>(base: &T, local: &T, server: &T) -> T {
+    if server != base {
+        server.clone()
+    } else {
+        local.clone()
+    }
+}
+
+fn merge_todo(base: &Todo, local: &Todo, server: &Todo) -> Todo {
+    Todo {
+        description: pick(&base.description, &local.description, &server.description),
+        notice: pick(&base.notice, &local.notice, &server.notice),
+        pin: pick(&base.pin, &local.pin, &server.pin),
+        completed: pick(&base.completed, &local.completed, &server.completed),
+        completed_at: pick(&base.completed_at, &local.completed_at, &server.completed_at),
+        repeat_type: pick(&base.repeat_type, &local.repeat_type, &server.repeat_type),
+        remind_time: pick(&base.remind_time, &local.remind_time, &server.remind_time),
+        estimated_pomo_count: pick(&base.estimated_pomo_count,
+                                   &local.estimated_pomo_count,
+                                   &server.estimated_pomo_count),
+        costed_pomo_count: pick(&base.costed_pomo_count,
+                                &local.costed_pomo_count,
+                                &server.costed_pomo_count),
+        ..server.clone()
+    }
+}
+
+fn merge_sub_todo(base: &SubTodo, local: &SubTodo, server: &SubTodo) -> SubTodo {
+    SubTodo {
+        description: pick(&base.description, &local.description, &server.description),
+        completed: pick(&base.completed, &local.completed, &server.completed),
+        completed_at: pick(&base.completed_at, &local.completed_at, &server.completed_at),
+        ..server.clone()
+    }
+}
+
+fn set<T: PartialEq + Clone>(from: &T, to: &T) -> Field<T> {
+    if from == to {
+        Field::Unchanged
+    } else {
+        Field::Set(to.clone())
+    }
+}
+
+fn set_or_clear<T: PartialEq + Clone>(from: &Option<T>, to: &Option<T>) -> Field<T> {
+    if from == to {
+        Field::Unchanged
+    } else {
+        Field::from(to.clone())
+    }
+}
+
+fn pomo_patch(from: &Pomo, to: &Pomo) -> PomoPatch {
+    PomoPatch { description: set(&from.description, &to.description) }
+}
+
+fn todo_patch(from: &Todo, to: &Todo) -> TodoPatch {
+    TodoPatch {
+        description: set(&from.description, &to.description),
+        notice: set_or_clear(&from.notice, &to.notice),
+        pin: set_or_clear(&from.pin, &to.pin),
+        completed: set_or_clear(&from.completed, &to.completed),
+        completed_at: set_or_clear(&from.completed_at, &to.completed_at),
+        repeat_type: set_or_clear(&from.repeat_type, &to.repeat_type),
+        remind_time: set_or_clear(&from.remind_time, &to.remind_time),
+        estimated_pomo_count: set_or_clear(&from.estimated_pomo_count, &to.estimated_pomo_count),
+        costed_pomo_count: set_or_clear(&from.costed_pomo_count, &to.costed_pomo_count),
+    }
+}
+
+fn sub_todo_patch(from: &SubTodo, to: &SubTodo) -> SubTodoPatch {
+    SubTodoPatch {
+        description: set(&from.description, &to.description),
+        completed: set_or_clear(&from.completed, &to.completed),
+        completed_at: set_or_clear(&from.completed_at, &to.completed_at),
+    }
+}
@@ -0,0 +1,158 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The `#hashtags` written in descriptions.
+//!
+//! A tag is a `#` followed by letters, digits, `_` or `-`, at the start of
+//! the description or after a space, a punctuation or a non-ASCII letter, so
+//! `写文档#项目` is tagged while `C#` and `issue#12` are not. Tags are
+//! compared case-insensitively and returned in lower case, without the `#`.
+//!
+//! # Example
+//!
+//! ```rust
+//! # use pomotodo::{Pomo, Tagged};
+//! # use pomotodo::tags;
+//! let pomo = Pomo {
+//!     description: "Write the docs #Work #rust".to_string(),
+//!     ..Default::default()
+//! };
+//!
+//! assert_eq!(vec!["work", "rust"], pomo.tags());
+//! assert_eq!("Write the docs", pomo.description_without_tags());
+//! assert_eq!(1, tags::with_tags(&[pomo], &["#work"]).len());
+//! ```
+
+use std::collections::BTreeMap;
+
+use pomo::Pomo;
+use todo::{Todo, SubTodo};
+use stats::{self, Bucket};
+
+/// A record with a description which may contain `#hashtags`.
+pub trait Tagged {
+    /// The description to find the tags in.
+    fn description(&self) -> &str;
+
+    /// The tags in lower case without the `#`, in order and deduplicated.
+    fn tags(&self) -> Vec<String> {
+        let mut tags = Vec::new();
+        for (start, end) in spans(self.description()) {
+            let tag = self.description()[start + 1..end].to_lowercase();
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        tags
+    }
+
+    /// The description with the tags removed and the spaces collapsed.
+    fn description_without_tags(&self) -> String {
+        let description = self.description();
+        let mut text = String::with_capacity(description.len());
+        let mut last = 0;
+        for (start, end) in spans(description) {
+            text.push_str(&description[last..start]);
+            text.push(' ');
+            last = end;
+        }
+        text.push_str(&description[last..]);
+
+        text.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    /// Whether the description has the `tag`, with or without the `#`.
+    fn has_tag(&self, tag: &str) -> bool {
+        let tag = normalize(tag);
+        self.tags().iter().any(|t| *t == tag)
+    }
+}
+
+impl Tagged for Pomo {
+    fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+impl Tagged for Todo {
+    fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+impl Tagged for SubTodo {
+    fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+/// The `items` having all the `tags`, with or without the `#`.
+pub fn with_tags<'a, T: Tagged>(items: &'a [T], tags: &[&str]) -> Vec<&'a T> {
+    items.iter().filter(|item| tags.iter().all(|tag| item.has_tag(tag))).collect()
+}
+
+/// The finished `Pomo`s of each tag, a `Pomo` with several tags counts
+/// towards each of them and one without any tag is left out.
+pub fn per_tag(pomos: &[Pomo]) -> BTreeMap<String, Bucket> {
+    let mut buckets = BTreeMap::new();
+    for pomo in pomos.iter().filter(|pomo| pomo.abandoned != Some(true)) {
+        for tag in pomo.tags() {
+            let bucket = buckets.entry(tag).or_insert_with(Bucket::default);
+            bucket.count += 1;
+            bucket.focus = bucket.focus + stats::span(pomo);
+        }
+    }
+    buckets
+}
+
+/// The byte ranges of the tags in `description`, including the `#`.
+fn spans(description: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    let mut prev = None;
+    let mut chars = description.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        if c == '#' && prev.map_or(true, is_boundary) {
+            let mut end = start + 1;
+            loop {
+                let (i, c) = match chars.peek() {
+                    Some(&(i, c)) if is_tag_char(c) => (i, c),
+                    _ => break,
+                };
+                end = i + c.len_utf8();
+                prev = Some(c);
+                chars.next();
+            }
+            if end > start + 1 {
+                spans.push((start, end));
+                continue;
+            }
+        }
+        prev = Some(c);
+    }
+
+    spans
+}
+
+/// Whether a tag may start after `c`.
+fn is_boundary(c: char) -> bool {
+    c != '#' && ((c as u32) > 0x7f || !is_tag_char(c))
+}
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-'
+}
+
+fn normalize(tag: &str) -> String {
+    tag.trim_left_matches('#').to_lowercase()
+}
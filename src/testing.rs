@@ -0,0 +1,721 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An in-process fake Pomotodo server for offline testing.
+//!
+//! This module is only available with the `testing` feature enabled.
+//! The [`FakeServer`](struct.FakeServer.html) listens on a random local
+//! port and implements the `account`, `pomos`, `todos` and `sub_todos`
+//! endpoints with in-memory state, so a [`Client`](../struct.Client.html)
+//! can be exercised end to end without network access.
+//!
+//! # Example
+//!
+//! ```rust
+//! # use pomotodo::Pomo;
+//! # use pomotodo::testing::FakeServer;
+//! #
+//! # fn run() {
+//! let server = FakeServer::start().unwrap();
+//! let client = server.client();
+//!
+//! let pomo = client.submit_pomo(&Pomo::default()).unwrap();
+//! assert_eq!(server.pomos().len(), 1);
+//! # }
+//! ```
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use uuid::Uuid;
+use chrono::prelude::*;
+use reqwest::Url;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::{self, Value};
+
+use client::Client;
+use account::Account;
+use pomo::Pomo;
+use todo::{Todo, SubTodo};
+
+/// The access token accepted by a server created with
+/// [`FakeServer::start`](struct.FakeServer.html#method.start).
+pub const TOKEN: &'static str = "fake-token";
+
+const API_VERSION: &'static str = "1";
+
+/// The fields of a `Pomo` allowed to be updated.
+const POMO_FIELDS: &'static [&'static str] = &["description"];
+
+/// The fields of a `Todo` allowed to be updated.
+const TODO_FIELDS: &'static [&'static str] = &["description",
+                                               "notice",
+                                               "pin",
+                                               "completed",
+                                               "completed_at",
+                                               "repeat_type",
+                                               "remind_time",
+                                               "estimated_pomo_count",
+                                               "costed_pomo_count"];
+
+/// The fields of a `SubTodo` allowed to be updated.
+const SUB_TODO_FIELDS: &'static [&'static str] = &["description", "completed", "completed_at"];
+
+/// A fake Pomotodo server running on a background thread.
+///
+/// The server is shut down when the `FakeServer` is dropped.
+#[derive(Debug)]
+pub struct FakeServer {
+    addr: SocketAddr,
+    token: String,
+    state: Arc<Mutex<State>>,
+    shutdown: Arc<AtomicBool>,
+}
+
+/// The in-memory records of a `FakeServer`.
+#[derive(Debug)]
+struct State {
+    account: Account,
+    pomos: HashMap<Uuid, Pomo>,
+    todos: HashMap<Uuid, Todo>,
+    sub_todos: HashMap<Uuid, SubTodo>,
+    page_size: Option<usize>,
+}
+
+/// A parsed HTTP request.
+#[derive(Debug)]
+struct Request {
+    method: String,
+    path: String,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+/// The HTTP response to write back.
+#[derive(Debug)]
+struct Reply {
+    status: u16,
+    body: String,
+}
+
+/// The endpoints served by a `FakeServer`.
+#[derive(Debug)]
+enum Route {
+    Account,
+    Pomos,
+    Pomo(Uuid),
+    Todos,
+    Todo(Uuid),
+    SubTodos(Uuid),
+    SubTodo(Uuid, Uuid),
+}
+
+impl FakeServer {
+    /// Starts a server accepting the [`TOKEN`](constant.TOKEN.html).
+    pub fn start() -> io::Result<FakeServer> {
+        FakeServer::with_token(TOKEN)
+    }
+
+    /// Starts a server accepting only the given access token.
+    pub fn with_token<T: Into<String>>(token: T) -> io::Result<FakeServer> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let server = FakeServer {
+            addr: listener.local_addr()?,
+            token: token.into(),
+            state: Arc::new(Mutex::new(State::new())),
+            shutdown: Arc::new(AtomicBool::new(false)),
+        };
+
+        let token = server.token.clone();
+        let state = server.state.clone();
+        let shutdown = server.shutdown.clone();
+        thread::spawn(move || for stream in listener.incoming() {
+                          if shutdown.load(Ordering::SeqCst) {
+                              break;
+                          }
+                          if let Ok(stream) = stream {
+                              let token = token.clone();
+                              let state = state.clone();
+                              thread::spawn(move || serve(&stream, &token, &state));
+                          }
+                      });
+
+        Ok(server)
+    }
+
+    /// The base URL of the server, e.g. `http://127.0.0.1:4000`.
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// The access token accepted by the server.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Constructs a [`Client`](../struct.Client.html) talking to the server.
+    pub fn client(&self) -> Client {
+        Client::with_base_url(self.token.as_str(), self.base_url())
+            .expect("the fake server has a valid base URL")
+    }
+
+    /// Replace the `Account` returned by the `account` endpoint.
+    pub fn set_account(&self, account: Account) {
+        self.state().account = account;
+    }
+
+    /// Limit the number of `Pomo`s and `Todo`s in a listing, the latest
+    /// ones are returned first. The listings are not limited by default.
+    pub fn set_page_size(&self, size: Option<usize>) {
+        self.state().page_size = size;
+    }
+
+    /// Store a `Pomo` as if it was submitted before, filling the `uuid`,
+    /// `created_at` and `updated_at` if missing.
+    pub fn insert_pomo(&self, mut pomo: Pomo) -> Pomo {
+        let now = Utc::now();
+        let uuid = pomo.uuid.unwrap_or_else(Uuid::new_v4);
+        pomo.uuid = Some(uuid);
+        pomo.created_at = pomo.created_at.or(Some(now));
+        pomo.updated_at = pomo.updated_at.or(Some(now));
+
+        self.state().pomos.insert(uuid, pomo.clone());
+        pomo
+    }
+
+    /// Store a `Todo` as if it was created before, filling the `uuid`,
+    /// `created_at` and `updated_at` if missing.
+    pub fn insert_todo(&self, mut todo: Todo) -> Todo {
+        let now = Utc::now();
+        let uuid = todo.uuid.unwrap_or_else(Uuid::new_v4);
+        todo.uuid = Some(uuid);
+        todo.created_at = todo.created_at.or(Some(now));
+        todo.updated_at = todo.updated_at.or(Some(now));
+
+        self.state().todos.insert(uuid, todo.clone());
+        todo
+    }
+
+    /// All the `Pomo`s stored on the server.
+    pub fn pomos(&self) -> Vec<Pomo> {
+        self.state().pomos.values().cloned().collect()
+    }
+
+    /// All the `Todo`s stored on the server.
+    pub fn todos(&self) -> Vec<Todo> {
+        self.state().todos.values().cloned().collect()
+    }
+
+    /// All the `SubTodo`s stored on the server.
+    pub fn sub_todos(&self) -> Vec<SubTodo> {
+        self.state().sub_todos.values().cloned().collect()
+    }
+
+    fn state(&self) -> MutexGuard<State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Drop for FakeServer {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // Wake up the listener thread blocked in `accept`.
+        let _ = TcpStream::connect(self.addr);
+    }
+}
+
+impl State {
+    fn new() -> State {
+        let account = json!({
+            "username": "pomotodo",
+            "email": "pomotodo@example.com",
+            "timezone": "UTC",
+            "register_time": "2017-01-01T00:00:00Z",
+            "pro_expires_time": "2099-01-01T00:00:00Z",
+        });
+
+        State {
+            account: serde_json::from_value(account).expect("the fake account is valid"),
+            pomos: HashMap::new(),
+            todos: HashMap::new(),
+            sub_todos: HashMap::new(),
+            page_size: None,
+        }
+    }
+
+    fn list_pomos(&self, query: &[(String, String)]) -> Reply {
+        let mut pomos = Vec::new();
+        for pomo in self.pomos.values() {
+            match pomo_matches(pomo, query) {
+                Ok(true) => pomos.push(pomo),
+                Ok(false) => {}
+                Err(reply) => return reply,
+            }
+        }
+
+        pomos.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+        pomos.truncate(self.page_size.unwrap_or(usize::max_value()));
+        Reply::json(&pomos)
+    }
+
+    fn create_pomo(&mut self, body: &[u8]) -> Reply {
+        let mut pomo: Pomo = match decode(body) {
+            Ok(pomo) => pomo,
+            Err(reply) => return reply,
+        };
+
+        let now = Utc::now();
+        let uuid = Uuid::new_v4();
+        pomo.uuid = Some(uuid);
+        pomo.created_at = Some(now);
+        pomo.updated_at = Some(now);
+        pomo.length = Some((pomo.ended_at - pomo.started_at).num_seconds().max(0) as u64);
+
+        let reply = Reply::json(&pomo);
+        self.pomos.insert(uuid, pomo);
+        reply
+    }
+
+    fn update_pomo(&mut self, uuid: Uuid, body: &[u8]) -> Reply {
+        let pomo = match self.pomos.get(&uuid) {
+            Some(pomo) => merge(pomo, body, POMO_FIELDS),
+            None => return Reply::not_found(uuid),
+        };
+
+        match pomo {
+            Ok(mut pomo) => {
+                pomo.updated_at = Some(Utc::now());
+                let reply = Reply::json(&pomo);
+                self.pomos.insert(uuid, pomo);
+                reply
+            }
+            Err(reply) => reply,
+        }
+    }
+
+    fn list_todos(&self, query: &[(String, String)]) -> Reply {
+        let mut todos = Vec::new();
+        for todo in self.todos.values() {
+            match todo_matches(todo, query) {
+                Ok(true) => todos.push(todo),
+                Ok(false) => {}
+                Err(reply) => return reply,
+            }
+        }
+
+        todos.sort_by(|a, b| (b.completed_at, b.created_at).cmp(&(a.completed_at, a.created_at)));
+        todos.truncate(self.page_size.unwrap_or(usize::max_value()));
+        Reply::json(&todos)
+    }
+
+    fn create_todo(&mut self, body: &[u8]) -> Reply {
+        let mut todo: Todo = match decode(body) {
+            Ok(todo) => todo,
+            Err(reply) => return reply,
+        };
+
+        let now = Utc::now();
+        let uuid = Uuid::new_v4();
+        todo.uuid = Some(uuid);
+        todo.created_at = Some(now);
+        todo.updated_at = Some(now);
+        todo.sub_todos = Some(Vec::new());
+
+        let reply = Reply::json(&todo);
+        self.todos.insert(uuid, todo);
+        reply
+    }
+
+    fn update_todo(&mut self, uuid: Uuid, body: &[u8]) -> Reply {
+        let todo = match self.todos.get(&uuid) {
+            Some(todo) => merge(todo, body, TODO_FIELDS).map(|patched| (patched, todo.sub_todos.clone())),
+            None => return Reply::not_found(uuid),
+        };
+
+        match todo {
+            Ok((mut todo, sub_todos)) => {
+                todo.sub_todos = sub_todos;
+                todo.updated_at = Some(Utc::now());
+                let reply = Reply::json(&todo);
+                self.todos.insert(uuid, todo);
+                reply
+            }
+            Err(reply) => reply,
+        }
+    }
+
+    fn delete_todo(&mut self, uuid: Uuid) -> Reply {
+        match self.todos.remove(&uuid) {
+            Some(_) => {
+                self.sub_todos.retain(|_, sub_todo| sub_todo.parent_uuid != Some(uuid));
+                Reply::empty()
+            }
+            None => Reply::not_found(uuid),
+        }
+    }
+
+    fn list_sub_todos(&self, parent: Uuid) -> Reply {
+        if !self.todos.contains_key(&parent) {
+            return Reply::not_found(parent);
+        }
+
+        let mut sub_todos: Vec<&SubTodo> = self.sub_todos
+            .values()
+            .filter(|sub_todo| sub_todo.parent_uuid == Some(parent))
+            .collect();
+        sub_todos.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+        Reply::json(&sub_todos)
+    }
+
+    fn create_sub_todo(&mut self, parent: Uuid, body: &[u8]) -> Reply {
+        if !self.todos.contains_key(&parent) {
+            return Reply::not_found(parent);
+        }
+
+        let mut sub_todo: SubTodo = match decode(body) {
+            Ok(sub_todo) => sub_todo,
+            Err(reply) => return reply,
+        };
+
+        let now = Utc::now();
+        let uuid = Uuid::new_v4();
+        sub_todo.uuid = Some(uuid);
+        sub_todo.parent_uuid = Some(parent);
+        sub_todo.created_at = Some(now);
+        sub_todo.updated_at = Some(now);
+
+        if let Some(todo) = self.todos.get_mut(&parent) {
+            todo.sub_todos.get_or_insert_with(Vec::new).push(uuid);
+        }
+
+        let reply = Reply::json(&sub_todo);
+        self.sub_todos.insert(uuid, sub_todo);
+        reply
+    }
+
+    fn sub_todo(&self, parent: Uuid, uuid: Uuid) -> Option<&SubTodo> {
+        self.sub_todos.get(&uuid).and_then(|sub_todo| if sub_todo.parent_uuid == Some(parent) {
+                                                 Some(sub_todo)
+                                             } else {
+                                                 None
+                                             })
+    }
+
+    fn update_sub_todo(&mut self, parent: Uuid, uuid: Uuid, body: &[u8]) -> Reply {
+        let sub_todo = match self.sub_todo(parent, uuid) {
+            Some(sub_todo) => merge(sub_todo, body, SUB_TODO_FIELDS),
+            None => return Reply::not_found(uuid),
+        };
+
+        match sub_todo {
+            Ok(mut sub_todo) => {
+                sub_todo.updated_at = Some(Utc::now());
+                let reply = Reply::json(&sub_todo);
+                self.sub_todos.insert(uuid, sub_todo);
+                reply
+            }
+            Err(reply) => reply,
+        }
+    }
+
+    fn delete_sub_todo(&mut self, parent: Uuid, uuid: Uuid) -> Reply {
+        if self.sub_todo(parent, uuid).is_none() {
+            return Reply::not_found(uuid);
+        }
+
+        self.sub_todos.remove(&uuid);
+        if let Some(todo) = self.todos.get_mut(&parent) {
+            if let Some(ref mut sub_todos) = todo.sub_todos {
+                sub_todos.retain(|sub_todo| *sub_todo != uuid);
+            }
+        }
+        Reply::empty()
+    }
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|&&(ref key, _)| key.eq_ignore_ascii_case(name))
+            .map(|&(_, ref value)| value.as_str())
+    }
+}
+
+impl Reply {
+    fn json<T: Serialize>(value: &T) -> Reply {
+        match serde_json::to_string(value) {
+            Ok(body) => Reply { status: 200, body: body },
+            Err(e) => Reply::error(500, &e.to_string()),
+        }
+    }
+
+    fn empty() -> Reply {
+        Reply {
+            status: 200,
+            body: String::new(),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Reply {
+        Reply {
+            status: status,
+            body: json!({ "message": message }).to_string(),
+        }
+    }
+
+    fn not_found(uuid: Uuid) -> Reply {
+        Reply::error(404, &format!("{} not found", uuid))
+    }
+}
+
+impl Route {
+    fn parse(segments: &[&str]) -> Option<Route> {
+        if segments.len() < 2 || segments[0] != API_VERSION {
+            return None;
+        }
+
+        let uuid = |index: usize| segments.get(index).and_then(|s| Uuid::parse_str(s).ok());
+        match (segments.len(), segments[1]) {
+            (2, "account") => Some(Route::Account),
+            (2, "pomos") => Some(Route::Pomos),
+            (2, "todos") => Some(Route::Todos),
+            (3, "pomos") => uuid(2).map(Route::Pomo),
+            (3, "todos") => uuid(2).map(Route::Todo),
+            (4, "todos") if segments[3] == "sub_todos" => uuid(2).map(Route::SubTodos),
+            (5, "todos") if segments[3] == "sub_todos" => {
+                match (uuid(2), uuid(4)) {
+                    (Some(parent), Some(uuid)) => Some(Route::SubTodo(parent, uuid)),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Handle a single request on `stream` and close it.
+fn serve(stream: &TcpStream, token: &str, state: &Mutex<State>) {
+    let reply = match read_request(stream) {
+        Ok(Some(request)) => handle(&request, token, state),
+        Ok(None) => return,
+        Err(e) => Reply::error(400, &e.to_string()),
+    };
+
+    let _ = write_reply(stream, &reply);
+}
+
+fn read_request(stream: &TcpStream) -> io::Result<Option<Request>> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+
+    let (method, path) = {
+        let mut parts = line.split_whitespace();
+        (parts.next().unwrap_or_default().to_owned(), parts.next().unwrap_or_default().to_owned())
+    };
+
+    let mut headers = Vec::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+
+        let header = line.trim_right();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(pos) = header.find(':') {
+            headers.push((header[..pos].trim().to_owned(), header[pos + 1..].trim().to_owned()));
+        }
+    }
+
+    let mut request = Request {
+        method: method,
+        path: path,
+        headers: headers,
+        body: Vec::new(),
+    };
+
+    let length: usize = request
+        .header("Content-Length")
+        .and_then(|len| len.parse().ok())
+        .unwrap_or(0);
+    request.body.resize(length, 0);
+    reader.read_exact(&mut request.body)?;
+
+    Ok(Some(request))
+}
+
+fn write_reply(mut stream: &TcpStream, reply: &Reply) -> io::Result<()> {
+    write!(stream,
+           "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\
+            Connection: close\r\n\r\n",
+           reply.status,
+           reason(reply.status),
+           reply.body.len())?;
+    stream.write_all(reply.body.as_bytes())?;
+    stream.flush()
+}
+
+fn handle(request: &Request, token: &str, state: &Mutex<State>) -> Reply {
+    let authorization = format!("token {}", token);
+    if request.header("Authorization") != Some(authorization.as_str()) {
+        return Reply::error(401, "invalid access token");
+    }
+
+    let url = match Url::parse(&format!("http://localhost{}", request.path)) {
+        Ok(url) => url,
+        Err(e) => return Reply::error(400, &e.to_string()),
+    };
+    let segments: Vec<&str> = url.path_segments()
+        .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+    let query: Vec<(String, String)> = url.query_pairs().into_owned().collect();
+
+    let route = match Route::parse(&segments) {
+        Some(route) => route,
+        None => return Reply::error(404, "unknown endpoint"),
+    };
+
+    let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+    let body = &request.body;
+    match (request.method.as_str(), route) {
+        ("GET", Route::Account) => Reply::json(&state.account),
+
+        ("GET", Route::Pomos) => state.list_pomos(&query),
+        ("POST", Route::Pomos) => state.create_pomo(body),
+        ("GET", Route::Pomo(uuid)) => {
+            state.pomos.get(&uuid).map_or_else(|| Reply::not_found(uuid), Reply::json)
+        }
+        ("PATCH", Route::Pomo(uuid)) => state.update_pomo(uuid, body),
+        ("DELETE", Route::Pomo(uuid)) => {
+            state.pomos.remove(&uuid).map_or_else(|| Reply::not_found(uuid), |_| Reply::empty())
+        }
+
+        ("GET", Route::Todos) => state.list_todos(&query),
+        ("POST", Route::Todos) => state.create_todo(body),
+        ("GET", Route::Todo(uuid)) => {
+            state.todos.get(&uuid).map_or_else(|| Reply::not_found(uuid), Reply::json)
+        }
+        ("PATCH", Route::Todo(uuid)) => state.update_todo(uuid, body),
+        ("DELETE", Route::Todo(uuid)) => state.delete_todo(uuid),
+
+        ("GET", Route::SubTodos(parent)) => state.list_sub_todos(parent),
+        ("POST", Route::SubTodos(parent)) => state.create_sub_todo(parent, body),
+        ("GET", Route::SubTodo(parent, uuid)) => {
+            state.sub_todo(parent, uuid).map_or_else(|| Reply::not_found(uuid), Reply::json)
+        }
+        ("PATCH", Route::SubTodo(parent, uuid)) => state.update_sub_todo(parent, uuid, body),
+        ("DELETE", Route::SubTodo(parent, uuid)) => state.delete_sub_todo(parent, uuid),
+
+        _ => Reply::error(405, "method not allowed"),
+    }
+}
+
+fn pomo_matches(pomo: &Pomo, query: &[(String, String)]) -> Result<bool, Reply> {
+    for &(ref key, ref value) in query {
+        let matched = match key.as_str() {
+            "abandoned" => pomo.abandoned.unwrap_or(false) == parse_bool(value)?,
+            "manual" => pomo.manual.unwrap_or(false) == parse_bool(value)?,
+            "started_later_than" => pomo.started_at > parse_time(value)?,
+            "started_earlier_than" => pomo.started_at < parse_time(value)?,
+            "ended_later_than" => pomo.ended_at > parse_time(value)?,
+            "ended_earlier_than" => pomo.ended_at < parse_time(value)?,
+            _ => true,
+        };
+
+        if !matched {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+fn todo_matches(todo: &Todo, query: &[(String, String)]) -> Result<bool, Reply> {
+    for &(ref key, ref value) in query {
+        let matched = match key.as_str() {
+            "completed" => todo.completed.unwrap_or(false) == parse_bool(value)?,
+            "completed_later_than" => {
+                let than = parse_time(value)?;
+                todo.completed_at.map_or(false, |at| at > than)
+            }
+            "completed_earlier_than" => {
+                let than = parse_time(value)?;
+                todo.completed_at.map_or(false, |at| at < than)
+            }
+            _ => true,
+        };
+
+        if !matched {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+fn parse_bool(value: &str) -> Result<bool, Reply> {
+    value.parse().map_err(|_| Reply::error(400, &format!("invalid boolean `{}`", value)))
+}
+
+fn parse_time(value: &str) -> Result<DateTime<Utc>, Reply> {
+    value.parse().map_err(|_| Reply::error(400, &format!("invalid time `{}`", value)))
+}
+
+fn decode<T: DeserializeOwned>(body: &[u8]) -> Result<T, Reply> {
+    serde_json::from_slice(body).map_err(|e| Reply::error(422, &e.to_string()))
+}
+
+/// Apply the allowed `fields` of the JSON object in `body` onto `item`.
+fn merge<T>(item: &T, body: &[u8], fields: &[&str]) -> Result<T, Reply>
+    where T: Serialize + DeserializeOwned
+{
+    let patch = match decode(body)? {
+        Value::Object(patch) => patch,
+        _ => return Err(Reply::error(422, "expected a JSON object")),
+    };
+
+    let mut value = serde_json::to_value(item).map_err(|e| Reply::error(500, &e.to_string()))?;
+    if let Value::Object(ref mut map) = value {
+        for (key, field) in patch {
+            if fields.contains(&key.as_str()) {
+                map.insert(key, field);
+            }
+        }
+    }
+
+    serde_json::from_value(value).map_err(|e| Reply::error(422, &e.to_string()))
+}
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        422 => "Unprocessable Entity",
+        _   => "Internal Server Error",
+    }
+}
@@ -0,0 +1,393 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A local pomodoro timer producing the [`Pomo`](../struct.Pomo.html)s to submit.
+//!
+//! The [`Timer`](struct.Timer.html) is a plain state machine without any
+//! thread or clock of its own: every transition takes the current time as
+//! `now`, and [`tick`](struct.Timer.html#method.tick) has to be called
+//! regularly to finish the running `Pomo` or break when its time is up.
+//!
+//! ```text
+//! Idle ── start ──> Running <── resume ── Paused
+//!                   │  └───── pause ───────┘
+//!                   ├── abandon ──> Abandoned
+//!                   └── tick ─────> Finished ── start_break ──> Break
+//!                                                                 │
+//! Idle <─────────────────────── tick / skip_break ────────────────┘
+//! ```
+//!
+//! `Finished` and `Abandoned` timers can `start` the next `Pomo` right away.
+//!
+//! # Example
+//!
+//! ```rust
+//! # extern crate chrono;
+//! # extern crate pomotodo;
+//! # use chrono::{Duration, TimeZone, Utc};
+//! # use pomotodo::timer::{Event, Timer, TimerConfig};
+//! #
+//! # fn run() -> Result<(), pomotodo::errors::Error> {
+//! let start = Utc.ymd(2017, 1, 1).and_hms(9, 0, 0);
+//!
+//! let mut timer = Timer::new(TimerConfig::default());
+//! timer.start("Write the report", start)?;
+//!
+//! if let Some(Event::Finished(pomo)) = timer.tick(start + Duration::minutes(25)) {
+//!     assert_eq!(start + Duration::minutes(25), pomo.ended_at);
+//! }
+//! # Ok(())
+//! # }
+//! # fn main() { run().unwrap() }
+//! ```
+
+use chrono::prelude::*;
+use chrono::Duration;
+use chrono_tz::Tz;
+
+use errors::*;
+use pomo::Pomo;
+
+/// The lengths used by a [`Timer`](struct.Timer.html).
+#[derive(Debug, Clone)]
+pub struct TimerConfig {
+    pomo_length: Duration,
+    short_break: Duration,
+    long_break: Duration,
+    long_break_every: u32,
+    utc_offset: FixedOffset,
+    timezone: Option<Tz>,
+}
+
+/// The state of a [`Timer`](struct.Timer.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Idle,
+    Running,
+    Paused,
+    Finished,
+    Abandoned,
+    Break(BreakKind),
+}
+
+/// The kind of a break taken after a finished `Pomo`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakKind {
+    Short,
+    Long,
+}
+
+/// An event emitted by a transition of a [`Timer`](struct.Timer.html).
+#[derive(Debug, Clone)]
+pub enum Event {
+    Started(DateTime<Utc>),
+    Paused(DateTime<Utc>),
+    Resumed(DateTime<Utc>),
+
+    /// The `Pomo` ran its full length, ready to be submitted.
+    Finished(Pomo),
+
+    /// The `Pomo` was abandoned, ready to be submitted as well.
+    Abandoned(Pomo),
+
+    BreakStarted(BreakKind, DateTime<Utc>),
+    BreakEnded(DateTime<Utc>),
+}
+
+/// A pomodoro timer, see the [module documentation](index.html).
+#[derive(Debug, Clone)]
+pub struct Timer {
+    config: TimerConfig,
+    state: State,
+    description: String,
+    started_at: Option<DateTime<Utc>>,
+    resumed_at: Option<DateTime<Utc>>,
+    elapsed: Duration,
+    break_ends_at: Option<DateTime<Utc>>,
+    finished: u32,
+}
+
+impl Default for TimerConfig {
+    fn default() -> TimerConfig {
+        TimerConfig {
+            pomo_length: Duration::minutes(25),
+            short_break: Duration::minutes(5),
+            long_break: Duration::minutes(15),
+            long_break_every: 4,
+            utc_offset: FixedOffset::east(0),
+            timezone: None,
+        }
+    }
+}
+
+impl TimerConfig {
+    /// Set the length of a `Pomo`, defaults to 25 minutes.
+    pub fn with_pomo_length(&mut self, length: Duration) -> &mut TimerConfig {
+        self.pomo_length = length;
+        self
+    }
+
+    /// Set the length of a short break, defaults to 5 minutes.
+    pub fn with_short_break(&mut self, length: Duration) -> &mut TimerConfig {
+        self.short_break = length;
+        self
+    }
+
+    /// Set the length of a long break, defaults to 15 minutes.
+    pub fn with_long_break(&mut self, length: Duration) -> &mut TimerConfig {
+        self.long_break = length;
+        self
+    }
+
+    /// Set how many finished `Pomo`s earn a long break, defaults to 4.
+    pub fn with_long_break_every(&mut self, pomos: u32) -> &mut TimerConfig {
+        self.long_break_every = ::std::cmp::max(pomos, 1);
+        self
+    }
+
+    /// Set the offset of the local times of the produced `Pomo`s, defaults to UTC.
+    pub fn with_utc_offset(&mut self, offset: FixedOffset) -> &mut TimerConfig {
+        self.utc_offset = offset;
+        self
+    }
+
+    /// Set the timezone of the local times of the produced `Pomo`s, e.g.
+    /// the one of the account, which overrides the `utc_offset` and
+    /// follows its DST changes.
+    pub fn with_timezone(&mut self, tz: Tz) -> &mut TimerConfig {
+        self.timezone = Some(tz);
+        self
+    }
+
+    /// The length of a `Pomo`.
+    pub fn pomo_length(&self) -> Duration {
+        self.pomo_length
+    }
+
+    /// The length of a break of `kind`.
+    pub fn break_length(&self, kind: BreakKind) -> Duration {
+        match kind {
+            BreakKind::Short => self.short_break,
+            BreakKind::Long => self.long_break,
+        }
+    }
+
+    /// The wall-clock time of `time` where the `Pomo`s are recorded.
+    fn local(&self, time: DateTime<Utc>) -> NaiveDateTime {
+        match self.timezone {
+            Some(tz) => time.with_timezone(&tz).naive_local(),
+            None => time.with_timezone(&self.utc_offset).naive_local(),
+        }
+    }
+}
+
+impl Timer {
+    /// Constructs an idle `Timer`.
+    pub fn new(config: TimerConfig) -> Timer {
+        Timer {
+            config: config,
+            state: State::Idle,
+            description: String::new(),
+            started_at: None,
+            resumed_at: None,
+            elapsed: Duration::zero(),
+            break_ends_at: None,
+            finished: 0,
+        }
+    }
+
+    /// The current state.
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// The lengths used by the timer.
+    pub fn config(&self) -> &TimerConfig {
+        &self.config
+    }
+
+    /// How many `Pomo`s have been finished so far.
+    pub fn finished_count(&self) -> u32 {
+        self.finished
+    }
+
+    /// The running time of the current `Pomo` at `now`, pauses excluded.
+    pub fn elapsed(&self, now: DateTime<Utc>) -> Duration {
+        match self.resumed_at {
+            Some(resumed_at) if self.state == State::Running => {
+                self.elapsed + ::std::cmp::max(now.signed_duration_since(resumed_at),
+                                               Duration::zero())
+            }
+            _ => self.elapsed,
+        }
+    }
+
+    /// The time left of the current `Pomo` or break at `now`,
+    /// `None` if neither is in progress.
+    pub fn remaining(&self, now: DateTime<Utc>) -> Option<Duration> {
+        match self.state {
+            State::Running | State::Paused => {
+                Some(::std::cmp::max(self.config.pomo_length - self.elapsed(now),
+                                     Duration::zero()))
+            }
+            State::Break(_) => {
+                self.break_ends_at
+                    .map(|ends_at| ::std::cmp::max(ends_at.signed_duration_since(now),
+                                                   Duration::zero()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Starts a new `Pomo` with the `description`.
+    ///
+    /// Allowed when `Idle`, `Finished` or `Abandoned`.
+    pub fn start<S: Into<String>>(&mut self, description: S, now: DateTime<Utc>)
+                                  -> Result<Event, Error> {
+        match self.state {
+            State::Idle | State::Finished | State::Abandoned => {}
+            _ => return Err(self.invalid("start")),
+        }
+
+        self.state = State::Running;
+        self.description = description.into();
+        self.started_at = Some(now);
+        self.resumed_at = Some(now);
+        self.elapsed = Duration::zero();
+        Ok(Event::Started(now))
+    }
+
+    /// Pauses the running `Pomo`.
+    pub fn pause(&mut self, now: DateTime<Utc>) -> Result<Event, Error> {
+        if self.state != State::Running {
+            return Err(self.invalid("pause"));
+        }
+
+        self.elapsed = self.elapsed(now);
+        self.resumed_at = None;
+        self.state = State::Paused;
+        Ok(Event::Paused(now))
+    }
+
+    /// Resumes the paused `Pomo`.
+    pub fn resume(&mut self, now: DateTime<Utc>) -> Result<Event, Error> {
+        if self.state != State::Paused {
+            return Err(self.invalid("resume"));
+        }
+
+        self.resumed_at = Some(now);
+        self.state = State::Running;
+        Ok(Event::Resumed(now))
+    }
+
+    /// Abandons the running or paused `Pomo`, which ends at `now`.
+    pub fn abandon(&mut self, now: DateTime<Utc>) -> Result<Event, Error> {
+        match self.state {
+            State::Running | State::Paused => {}
+            _ => return Err(self.invalid("abandon")),
+        }
+
+        self.elapsed = self.elapsed(now);
+        self.resumed_at = None;
+        self.state = State::Abandoned;
+        Ok(Event::Abandoned(self.pomo(now, true)))
+    }
+
+    /// Starts the break following a finished `Pomo`, a long one
+    /// after every `long_break_every` finished `Pomo`s.
+    pub fn start_break(&mut self, now: DateTime<Utc>) -> Result<Event, Error> {
+        if self.state != State::Finished {
+            return Err(self.invalid("start_break"));
+        }
+
+        let kind = if self.finished % self.config.long_break_every == 0 {
+            BreakKind::Long
+        } else {
+            BreakKind::Short
+        };
+        self.state = State::Break(kind);
+        self.break_ends_at = Some(now + self.config.break_length(kind));
+        Ok(Event::BreakStarted(kind, now))
+    }
+
+    /// Ends the break early.
+    pub fn skip_break(&mut self, now: DateTime<Utc>) -> Result<Event, Error> {
+        match self.state {
+            State::Break(_) => {}
+            _ => return Err(self.invalid("skip_break")),
+        }
+
+        self.state = State::Idle;
+        self.break_ends_at = None;
+        Ok(Event::BreakEnded(now))
+    }
+
+    /// Finishes the running `Pomo` or the break if its time is up at `now`.
+    ///
+    /// The produced `Pomo` ends when its time was up, which is earlier than
+    /// `now` if the timer is not ticked in time.
+    pub fn tick(&mut self, now: DateTime<Utc>) -> Option<Event> {
+        match self.state {
+            State::Running if self.elapsed(now) >= self.config.pomo_length => {
+                let resumed_at = self.resumed_at.unwrap_or(now);
+                let ended_at = resumed_at + (self.config.pomo_length - self.elapsed);
+
+                self.elapsed = self.config.pomo_length;
+                self.resumed_at = None;
+                self.state = State::Finished;
+                self.finished += 1;
+                Some(Event::Finished(self.pomo(ended_at, false)))
+            }
+            State::Break(_) => {
+                match self.break_ends_at {
+                    Some(ends_at) if ends_at <= now => {
+                        self.state = State::Idle;
+                        self.break_ends_at = None;
+                        Some(Event::BreakEnded(ends_at))
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Fills a `Pomo` ending at `ended_at`. The `length` is the whole span
+    /// in seconds, pauses included, as server would recalculate it.
+    fn pomo(&self, ended_at: DateTime<Utc>, abandoned: bool) -> Pomo {
+        let started_at = self.started_at.unwrap_or(ended_at);
+        let length = ended_at.signed_duration_since(started_at).num_seconds();
+
+        Pomo {
+            uuid: None,
+            created_at: None,
+            updated_at: None,
+            description: self.description.clone(),
+            started_at: started_at,
+            ended_at: ended_at,
+            local_started_at: Some(self.config.local(started_at)),
+            local_ended_at: Some(self.config.local(ended_at)),
+            length: Some(::std::cmp::max(length, 0) as u64),
+            abandoned: Some(abandoned),
+            manual: Some(true),
+        }
+    }
+
+    fn invalid(&self, action: &'static str) -> Error {
+        ErrorKind::InvalidTransition {
+                state: self.state,
+                action: action,
+            }
+            .into()
+    }
+}
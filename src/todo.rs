@@ -14,6 +14,10 @@
 use uuid::Uuid;
 use chrono::prelude::*;
 
+use errors::*;
+use query::Query;
+use validate::ensure_valid;
+
 /// The repeat type of [`Todo`](struct.Todo.html).
 #[derive(Debug, Clone, Eq, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "snake_case")]
@@ -91,13 +95,30 @@ pub struct Todo {
 }
 
 /// A builder to construct the properties of a [`Todo`](struct.Todo.html).
+///
+/// # Example
+///
+/// ```rust
+/// # use pomotodo::{Todo, RepeatType};
+/// #
+/// # fn run() -> Result<(), pomotodo::errors::Error> {
+/// let mut builder = Todo::builder();
+/// builder
+///     .description("Water the plants")
+///     .repeat_type(RepeatType::EachWeek)
+///     .estimated_pomo_count(1);
+/// let todo = builder.finish()?;
+/// # Ok(())
+/// # }
+/// # fn main() { run().unwrap() }
+/// ```
 #[derive(Debug)]
 pub struct TodoBuilder {
     todo: Todo,
 }
 
 /// The parameters used in getting [`Todo`](struct.Todo.html)s.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct TodoParameter {
     completed: Option<bool>,
     completed_later_than: Option<DateTime<Utc>>,
@@ -202,9 +223,60 @@ impl TodoBuilder {
         self
     }
 
+    /// Set the `notice` property.
+    pub fn notice<S: Into<String>>(&mut self, notice: S) -> &mut TodoBuilder {
+        self.todo.notice = Some(notice.into());
+        self
+    }
+
+    /// Set the `pin` property.
+    pub fn pin(&mut self, pin: bool) -> &mut TodoBuilder {
+        self.todo.pin = Some(pin);
+        self
+    }
+
+    /// Set the `completed` property.
+    pub fn completed(&mut self, completed: bool) -> &mut TodoBuilder {
+        self.todo.completed = Some(completed);
+        self
+    }
+
+    /// Set the `completed_at` property.
+    pub fn completed_at(&mut self, time: DateTime<Utc>) -> &mut TodoBuilder {
+        self.todo.completed_at = Some(time);
+        self
+    }
+
+    /// Set the `repeat_type` property.
+    pub fn repeat_type(&mut self, repeat: RepeatType) -> &mut TodoBuilder {
+        self.todo.repeat_type = Some(repeat);
+        self
+    }
+
+    /// Set the `remind_time` property.
+    pub fn remind_time(&mut self, time: DateTime<Utc>) -> &mut TodoBuilder {
+        self.todo.remind_time = Some(time);
+        self
+    }
+
+    /// Set the `estimated_pomo_count` property.
+    pub fn estimated_pomo_count(&mut self, count: u64) -> &mut TodoBuilder {
+        self.todo.estimated_pomo_count = Some(count);
+        self
+    }
+
+    /// Set the `costed_pomo_count` property.
+    pub fn costed_pomo_count(&mut self, count: u64) -> &mut TodoBuilder {
+        self.todo.costed_pomo_count = Some(count);
+        self
+    }
+
     /// Build a [`Todo`](struct.Todo.html).
-    pub fn finish(self) -> Todo {
-        self.todo
+    ///
+    /// Fails with `ErrorKind::Invalid` if the `Todo` breaks any creation rule.
+    pub fn finish(self) -> Result<Todo, Error> {
+        ensure_valid(&self.todo)?;
+        Ok(self.todo)
     }
 }
 
@@ -227,20 +299,41 @@ impl TodoParameter {
         self
     }
 
-    /// Convert [`TodoParameter`](struct.TodoParameter.html) to query string.
-    pub fn to_query(&self) -> String {
-        let mut params: Vec<String> = Vec::new();
+    /// Whether the `todo` matches the parameters, as server filters it.
+    /// A missing `completed` counts as `false`.
+    pub fn matches(&self, todo: &Todo) -> bool {
+        self.completed.map_or(true, |completed| todo.completed.unwrap_or(false) == completed) &&
+        self.completed_later_than
+            .map_or(true, |than| todo.completed_at.map_or(false, |at| at > than)) &&
+        self.completed_earlier_than
+            .map_or(true, |than| todo.completed_at.map_or(false, |at| at < than))
+    }
+
+    /// The exclusive bounds of `completed_at`, later and earlier.
+    pub(crate) fn completed_bounds(&self) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+        (self.completed_later_than, self.completed_earlier_than)
+    }
+
+    /// Convert [`TodoParameter`](struct.TodoParameter.html) to a typed [`Query`](struct.Query.html).
+    pub fn query(&self) -> Query {
+        let mut query = Query::new();
+
         if let Some(completed) = self.completed {
-            params.push(format!("completed={}", completed));
+            query.push_bool("completed", completed);
         }
         if let Some(completed_later_than) = self.completed_later_than {
-            params.push(format!("completed_later_than={}", completed_later_than));
+            query.push_time("completed_later_than", completed_later_than);
         }
         if let Some(completed_earlier_than) = self.completed_earlier_than {
-            params.push(format!("completed_earlier_than={}", completed_earlier_than));
+            query.push_time("completed_earlier_than", completed_earlier_than);
         }
 
-        params.join("&")
+        query
+    }
+
+    /// Convert [`TodoParameter`](struct.TodoParameter.html) to a percent-encoded query string.
+    pub fn to_query(&self) -> String {
+        self.query().to_string()
     }
 }
 
@@ -259,9 +352,30 @@ impl SubTodoBuilder {
         self
     }
 
+    /// Set the `parent_uuid` property, which is dropped by server.
+    pub fn parent_uuid<U: Into<Uuid>>(&mut self, parent: U) -> &mut SubTodoBuilder {
+        self.sub_todo.parent_uuid = Some(parent.into());
+        self
+    }
+
+    /// Set the `completed` property.
+    pub fn completed(&mut self, completed: bool) -> &mut SubTodoBuilder {
+        self.sub_todo.completed = Some(completed);
+        self
+    }
+
+    /// Set the `completed_at` property.
+    pub fn completed_at(&mut self, time: DateTime<Utc>) -> &mut SubTodoBuilder {
+        self.sub_todo.completed_at = Some(time);
+        self
+    }
+
     /// Build a [`SubTodo`](struct.SubTodo.html).
-    pub fn finish(self) -> SubTodo {
-        self.sub_todo
+    ///
+    /// Fails with `ErrorKind::Invalid` if the `SubTodo` breaks any creation rule.
+    pub fn finish(self) -> Result<SubTodo, Error> {
+        ensure_valid(&self.sub_todo)?;
+        Ok(self.sub_todo)
     }
 }
 
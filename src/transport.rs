@@ -0,0 +1,121 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Debug;
+use std::io::Read;
+
+use reqwest::{Method, Url};
+use reqwest::header::Headers;
+
+use errors::*;
+
+/// An HTTP request made by the [`Client`](struct.Client.html).
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+    pub method: Method,
+
+    pub url: Url,
+
+    pub headers: Vec<(String, String)>,
+
+    pub body: Option<Vec<u8>>,
+}
+
+/// An HTTP response returned by a [`Transport`](trait.Transport.html).
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    pub status: u16,
+
+    pub headers: Vec<(String, String)>,
+
+    pub body: Vec<u8>,
+}
+
+/// The HTTP stack used by the [`Client`](struct.Client.html).
+///
+/// The `Client` builds every request, including the `Authorization`
+/// and `Content-Type` headers, and hands it to its `Transport`.
+/// Implement this trait to record requests, answer them from memory or
+/// send them through another HTTP library, then install it with
+/// [`ClientBuilder::transport`](struct.ClientBuilder.html#method.transport).
+///
+/// A response with any status code should be returned as `Ok`,
+/// `Err` is reserved for failures to deliver the request.
+pub trait Transport: Debug + Send + Sync {
+    /// Sends the `request` and waits for the whole response.
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse, Error>;
+}
+
+/// The default [`Transport`](trait.Transport.html), backed by a `reqwest::Client`.
+#[derive(Debug, Clone)]
+pub struct ReqwestTransport {
+    inner: ::reqwest::Client,
+}
+
+impl HttpResponse {
+    /// Returns the value of the header `name`, compared case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|&&(ref key, _)| key.eq_ignore_ascii_case(name))
+            .map(|&(_, ref value)| value.as_str())
+    }
+}
+
+impl Default for ReqwestTransport {
+    fn default() -> ReqwestTransport {
+        ReqwestTransport { inner: ::reqwest::Client::new() }
+    }
+}
+
+impl ReqwestTransport {
+    /// Constructs a new `ReqwestTransport`.
+    pub fn new() -> ReqwestTransport {
+        ReqwestTransport::default()
+    }
+}
+
+impl From<::reqwest::Client> for ReqwestTransport {
+    fn from(client: ::reqwest::Client) -> ReqwestTransport {
+        ReqwestTransport { inner: client }
+    }
+}
+
+impl Transport for ReqwestTransport {
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse, Error> {
+        let mut headers = Headers::new();
+        for (name, value) in request.headers {
+            headers.set_raw(name, value);
+        }
+
+        let mut builder = self.inner.request(request.method, request.url);
+        builder.headers(headers);
+        if let Some(body) = request.body {
+            builder.body(body);
+        }
+
+        let mut resp = builder.send()?;
+        let headers = resp.headers()
+            .iter()
+            .map(|header| (header.name().to_owned(), header.value_string()))
+            .collect();
+        let mut body = Vec::new();
+        resp.read_to_end(&mut body)?;
+
+        Ok(HttpResponse {
+               status: resp.status().as_u16(),
+               headers: headers,
+               body: body,
+           })
+    }
+}
@@ -0,0 +1,176 @@
+// Copyright 2017 Kam Y. Tse
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use errors::*;
+use pomo::Pomo;
+use todo::{Todo, SubTodo};
+use patch::{Field, PomoPatch, TodoPatch, SubTodoPatch};
+
+/// A rule of Pomotodo server broken by a payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// The field is not allowed in creating, e.g. the `uuid`.
+    NotAllowed(&'static str),
+
+    /// The required field is missing or empty.
+    Required(&'static str),
+
+    /// The required field is cleared by a patch.
+    Cleared(&'static str),
+
+    /// The `manual` of a submitted `Pomo` is not `true`.
+    NotManual,
+
+    /// The `ended_at` of a `Pomo` is earlier than its `started_at`.
+    EndedBeforeStarted,
+
+    /// The field is too large to be represented, e.g. the `length`.
+    OutOfRange(&'static str),
+}
+
+/// Checks a payload against the rules of Pomotodo server before it is sent.
+///
+/// The [`Client`](struct.Client.html) validates every `Pomo`, `Todo`,
+/// `SubTodo` and patch it creates or updates, and rejects the invalid
+/// ones with `ErrorKind::Invalid` without making any request.
+pub trait Validate {
+    /// Returns every rule broken by the payload, empty if it is valid.
+    fn validate(&self) -> Vec<Violation>;
+
+    /// Whether the payload breaks no rule.
+    fn is_valid(&self) -> bool {
+        self.validate().is_empty()
+    }
+}
+
+impl Validate for Pomo {
+    fn validate(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+
+        if self.uuid.is_some() {
+            violations.push(Violation::NotAllowed("uuid"));
+        }
+        if self.created_at.is_some() {
+            violations.push(Violation::NotAllowed("created_at"));
+        }
+        if self.updated_at.is_some() {
+            violations.push(Violation::NotAllowed("updated_at"));
+        }
+        if self.description.trim().is_empty() {
+            violations.push(Violation::Required("description"));
+        }
+        if self.manual != Some(true) {
+            violations.push(Violation::NotManual);
+        }
+        if self.ended_at < self.started_at {
+            violations.push(Violation::EndedBeforeStarted);
+        }
+
+        violations
+    }
+}
+
+impl Validate for Todo {
+    fn validate(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+
+        if self.uuid.is_some() {
+            violations.push(Violation::NotAllowed("uuid"));
+        }
+        if self.created_at.is_some() {
+            violations.push(Violation::NotAllowed("created_at"));
+        }
+        if self.updated_at.is_some() {
+            violations.push(Violation::NotAllowed("updated_at"));
+        }
+        if self.sub_todos.is_some() {
+            violations.push(Violation::NotAllowed("sub_todos"));
+        }
+        if self.description.trim().is_empty() {
+            violations.push(Violation::Required("description"));
+        }
+
+        violations
+    }
+}
+
+impl Validate for SubTodo {
+    fn validate(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+
+        if self.uuid.is_some() {
+            violations.push(Violation::NotAllowed("uuid"));
+        }
+        if self.created_at.is_some() {
+            violations.push(Violation::NotAllowed("created_at"));
+        }
+        if self.updated_at.is_some() {
+            violations.push(Violation::NotAllowed("updated_at"));
+        }
+        if self.description.trim().is_empty() {
+            violations.push(Violation::Required("description"));
+        }
+
+        violations
+    }
+}
+
+impl Validate for PomoPatch {
+    fn validate(&self) -> Vec<Violation> {
+        description(&self.description).into_iter().collect()
+    }
+}
+
+impl Validate for TodoPatch {
+    fn validate(&self) -> Vec<Violation> {
+        description(&self.description).into_iter().collect()
+    }
+}
+
+impl Validate for SubTodoPatch {
+    fn validate(&self) -> Vec<Violation> {
+        description(&self.description).into_iter().collect()
+    }
+}
+
+impl ::std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Violation::NotAllowed(field) => write!(f, "`{}` is not allowed", field),
+            Violation::Required(field) => write!(f, "`{}` is required", field),
+            Violation::Cleared(field) => write!(f, "`{}` can not be cleared", field),
+            Violation::NotManual => write!(f, "`manual` must be true"),
+            Violation::EndedBeforeStarted => write!(f, "`ended_at` is earlier than `started_at`"),
+            Violation::OutOfRange(field) => write!(f, "`{}` is out of range", field),
+        }
+    }
+}
+
+/// Rejects the `payload` with `ErrorKind::Invalid` if it breaks any rule.
+pub(crate) fn ensure_valid<V: Validate>(payload: &V) -> Result<(), Error> {
+    let violations = payload.validate();
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(ErrorKind::Invalid(violations).into())
+    }
+}
+
+/// The patched `description` can be changed, but not emptied.
+fn description(field: &Field<String>) -> Option<Violation> {
+    match *field {
+        Field::Clear => Some(Violation::Cleared("description")),
+        Field::Set(ref desc) if desc.trim().is_empty() => Some(Violation::Required("description")),
+        _ => None,
+    }
+}
@@ -12,48 +12,1289 @@
 //See the License for the specific language governing permissions and
 //limitations under the License.
 
-extern crate pomotodo;
+extern crate uuid;
+extern crate chrono;
 extern crate serde_json;
+extern crate pomotodo;
+#[cfg(feature = "async")]
+extern crate tokio_core;
+
+use std::time::{Duration, Instant};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use uuid::Uuid;
+use chrono::{DateTime, TimeZone, Utc};
+
+use pomotodo::errors::{Error, ErrorKind};
+use pomotodo::{Client, Transport, HttpRequest, HttpResponse, RetryPolicy, RateLimiter};
+use pomotodo::{Pomo, Todo, SubTodo, RepeatType, PomoParameter, TodoParameter};
+use pomotodo::{Field, TodoPatch, Validate, Violation};
+
+/// A `Transport` answering `503` to the first `failures` requests.
+#[derive(Debug)]
+struct FlakyTransport {
+    failures: usize,
+    sent: AtomicUsize,
+}
 
-use pomotodo::session::Session;
-use pomotodo::pomo::Pomo;
-use pomotodo::todo::{Todo, SubTodo};
+impl Transport for FlakyTransport {
+    fn send(&self, _: HttpRequest) -> Result<HttpResponse, Error> {
+        let (status, body) = if self.sent.fetch_add(1, Ordering::SeqCst) < self.failures {
+            (503, "{}")
+        } else {
+            (200,
+             r#"{"username": "pomotodo", "email": "pomotodo@example.com", "timezone": "UTC",
+                 "register_time": "2017-01-01T00:00:00Z",
+                 "pro_expires_time": "2099-01-01T00:00:00Z"}"#)
+        };
+
+        Ok(HttpResponse {
+               status: status,
+               headers: Vec::new(),
+               body: body.as_bytes().to_vec(),
+           })
+    }
+}
+
+/// A `Transport` recording the URLs requested, answering an empty list.
+#[derive(Debug, Default)]
+struct RecordingTransport {
+    urls: Arc<Mutex<Vec<String>>>,
+}
+
+impl Transport for RecordingTransport {
+    fn send(&self, request: HttpRequest) -> Result<HttpResponse, Error> {
+        self.urls.lock().unwrap().push(request.url.to_string());
+
+        Ok(HttpResponse {
+               status: 200,
+               headers: Vec::new(),
+               body: b"[]".to_vec(),
+           })
+    }
+}
+
+/// A `Transport` answering `429` with a day long `Retry-After` to the
+/// first request, then an empty list.
+#[derive(Debug, Default)]
+struct RateLimitedTransport {
+    sent: AtomicUsize,
+}
+
+impl Transport for RateLimitedTransport {
+    fn send(&self, _: HttpRequest) -> Result<HttpResponse, Error> {
+        let (status, headers) = if self.sent.fetch_add(1, Ordering::SeqCst) == 0 {
+            (429, vec![("Retry-After".to_owned(), "86400".to_owned())])
+        } else {
+            (200, Vec::new())
+        };
+
+        Ok(HttpResponse {
+               status: status,
+               headers: headers,
+               body: b"[]".to_vec(),
+           })
+    }
+}
+
+fn flaky_client(failures: usize, attempts: u32) -> Client {
+    let mut policy = RetryPolicy::default();
+    policy.with_max_attempts(attempts).with_base(Duration::from_millis(1));
+
+    let mut builder = Client::builder("token");
+    builder
+        .transport(FlakyTransport {
+                       failures: failures,
+                       sent: AtomicUsize::new(0),
+                   })
+        .retry(policy);
+    builder.finish().unwrap()
+}
+
+/// A time in 2017, in UTC.
+fn at(month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+    Utc.ymd(2017, month, day).and_hms(hour, minute, 0)
+}
+
+/// A `Pomo` started at `started_at` and lasting `minutes`.
+fn pomo(description: &str, started_at: DateTime<Utc>, minutes: u64, abandoned: bool) -> Pomo {
+    let mut builder = Pomo::builder();
+    builder
+        .description(description)
+        .started_at(started_at)
+        .length(minutes * 60)
+        .abandoned(abandoned);
+    builder.finish().unwrap()
+}
+
+/// 09:00 of a day, in UTC.
+fn morning(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+    Utc.ymd(year, month, day).and_hms(9, 0, 0)
+}
+
+/// A `Todo` with a `uuid`, reminded at `remind_time` and repeated by `repeat_type`.
+fn todo(description: &str,
+        remind_time: Option<DateTime<Utc>>,
+        repeat_type: Option<RepeatType>)
+        -> Todo {
+    Todo {
+        uuid: Some(Uuid::new_v4()),
+        description: description.to_string(),
+        remind_time: remind_time,
+        repeat_type: repeat_type,
+        ..Default::default()
+    }
+}
 
 #[test]
-fn test_session() {
-    let sess = Session::with_token("Your token here").unwrap();
+fn test_retry() {
+    assert!(flaky_client(2, 3).account().is_ok());
+    assert!(flaky_client(3, 3).account().is_err());
+}
 
-    let mut pomo = sess.create_pomo(&Pomo { ..Default::default() }).unwrap();
-    let patched_pomo = sess.update_pomo(&pomo.uuid.unwrap(), "Test pomo patch".to_string())
-        .unwrap();
-    pomo.description = "Test pomo patch".to_string();
+#[test]
+fn test_retry_after_cap() {
+    let mut policy = RetryPolicy::default();
+    policy.with_cap(Duration::from_millis(10));
+
+    let mut builder = Client::builder("token");
+    builder.transport(RateLimitedTransport::default()).retry(policy);
+    let client = builder.finish().unwrap();
+
+    let start = Instant::now();
+    assert!(client.pomos(PomoParameter::default()).unwrap().is_empty());
+    assert!(start.elapsed() < Duration::from_secs(5));
+}
+
+#[test]
+fn test_client_builder() {
+    let mut builder = Client::builder("token");
+    builder.base_url("http://localhost:8080/").api_version("/2/");
+    let client = builder.finish().unwrap();
+    assert_eq!("http://localhost:8080/2/", client.root_url().as_str());
+
+    let default = Client::new("token");
+    assert_eq!("https://api.pomotodo.com/1/", default.root_url().as_str());
+
+    let mut builder = Client::builder("token");
+    builder.base_url("not a url");
+    assert!(builder.finish().is_err());
+}
+
+#[test]
+fn test_transport() {
+    let urls = Arc::new(Mutex::new(Vec::new()));
+    let mut builder = Client::builder("token");
+    builder.transport(RecordingTransport { urls: urls.clone() });
+    let client = builder.finish().unwrap();
+
+    let parent = Uuid::new_v4();
+    assert!(client.subtodos(parent).unwrap().is_empty());
+    assert_eq!(vec![format!("https://api.pomotodo.com/1/todos/{}/sub_todos", parent)],
+               *urls.lock().unwrap());
+}
+
+#[cfg(feature = "testing")]
+mod fake {
+    use uuid::Uuid;
+    use pomotodo::errors::{Error, ErrorKind};
+    use pomotodo::testing::FakeServer;
+    use chrono::{Duration, TimeZone, Utc};
+    use pomotodo::{Client, Pomo, Todo, SubTodo, PomoParameter};
+    use pomotodo::{Field, PomoPatch, TodoPatch, SubTodoPatch};
+
+    #[test]
+    fn test_client() {
+        let server = FakeServer::start().unwrap();
+        let client = server.client();
+
+        assert!(client.account().is_ok());
+
+        let pomo = client.submit_pomo(&Pomo { ..Default::default() }).unwrap();
+        let patch = PomoPatch::description("Test pomo patch");
+        let patched_pomo = client.update_pomo(pomo.uuid.unwrap(), &patch).unwrap();
+        assert_eq!("Test pomo patch", patched_pomo.description);
+
+        assert!(client.delete_pomo(pomo.uuid.unwrap()).is_ok());
+        assert!(server.pomos().is_empty());
+
+        let todo = client.create_todo(&Todo { ..Default::default() }).unwrap();
+        let patch = TodoPatch {
+            completed: Field::Set(true),
+            ..Default::default()
+        };
+        let patched_todo = client.update_todo(todo.uuid.unwrap(), &patch).unwrap();
+        assert_eq!(todo.description, patched_todo.description);
+        assert_eq!(Some(true), patched_todo.completed);
+
+        let sub_todo = client.create_subtodo(todo.uuid.unwrap(), &SubTodo { ..Default::default() })
+            .unwrap();
+        let patch = SubTodoPatch {
+            description: Field::Set("Test sub todo patch".to_string()),
+            ..Default::default()
+        };
+        let patched_sub_todo = client
+            .update_subtodo(todo.uuid.unwrap(), sub_todo.uuid.unwrap(), &patch)
+            .unwrap();
+        assert_eq!("Test sub todo patch", patched_sub_todo.description);
+        assert_eq!(1, client.subtodos(todo.uuid.unwrap()).unwrap().len());
+
+        assert!(client.delete_subtodo(todo.uuid.unwrap(), sub_todo.uuid.unwrap()).is_ok());
+        assert!(client.delete_todo(todo.uuid.unwrap()).is_ok());
+        assert!(server.todos().is_empty());
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn test_async_client() {
+        use tokio_core::reactor::Core;
+        use pomotodo::TodoParameter;
+
+        let server = FakeServer::start().unwrap();
+        let mut core = Core::new().unwrap();
+        let mut builder = Client::builder(server.token());
+        builder.base_url(server.base_url());
+        let client = builder.finish_async(&core.handle()).unwrap();
+
+        assert!(core.run(client.account()).is_ok());
+        let todo = Todo {
+            description: "Async".to_string(),
+            ..Default::default()
+        };
+        let created = core.run(client.create_todo(&todo)).unwrap();
+        assert_eq!(vec![created.uuid], server.todos().iter().map(|t| t.uuid).collect::<Vec<_>>());
+
+        let todos = core.run(client.todos(TodoParameter::default())).unwrap();
+        assert_eq!(vec!["Async"], todos.iter().map(|t| t.description.as_str()).collect::<Vec<_>>());
+        match core.run(client.todo(Uuid::new_v4())) {
+            Err(Error(ErrorKind::NotFound(_), _)) => {}
+            other => panic!("expected NotFound, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_pomos_iter() {
+        let server = FakeServer::start().unwrap();
+        server.set_page_size(Some(2));
+
+        let start = Utc.ymd(2017, 1, 1).and_hms(9, 0, 0);
+        for i in 0..5 {
+            server.insert_pomo(Pomo {
+                                   started_at: start + Duration::hours(i),
+                                   ended_at: start + Duration::hours(i) + Duration::minutes(25),
+                                   ..Default::default()
+                               });
+        }
+
+        let mut param = PomoParameter::default();
+        param.with_manual(true);
+        let pomos: Vec<Pomo> = server.client().pomos_iter(param).map(|pomo| pomo.unwrap()).collect();
+
+        assert_eq!(5, pomos.len());
+        assert!(pomos.windows(2).all(|pair| pair[0].started_at > pair[1].started_at));
+    }
+
+    #[test]
+    fn test_pomos_iter_same_start() {
+        let server = FakeServer::start().unwrap();
+        server.set_page_size(Some(2));
+
+        let start = Utc.ymd(2017, 1, 1).and_hms(9, 0, 0);
+        for &hours in &[3, 2, 2, 0] {
+            server.insert_pomo(Pomo {
+                                   started_at: start + Duration::hours(hours),
+                                   ended_at: start + Duration::hours(hours) + Duration::minutes(25),
+                                   ..Default::default()
+                               });
+        }
+
+        let mut param = PomoParameter::default();
+        param.with_manual(true);
+        let pomos: Vec<Pomo> = server.client().pomos_iter(param).map(|pomo| pomo.unwrap()).collect();
+
+        assert_eq!(4, pomos.len());
+        for (i, pomo) in pomos.iter().enumerate() {
+            assert!(pomos[i + 1..].iter().all(|other| other.uuid != pomo.uuid));
+        }
+        assert!(pomos.windows(2).all(|pair| pair[0].started_at >= pair[1].started_at));
+    }
+
+    #[test]
+    fn test_bad_token() {
+        let server = FakeServer::start().unwrap();
+        let client = Client::with_base_url("bad token", server.base_url()).unwrap();
+
+        match client.account() {
+            Err(Error(ErrorKind::Unauthorized, _)) => {}
+            other => panic!("expected Unauthorized, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_not_found() {
+        let server = FakeServer::start().unwrap();
+        let client = server.client();
+        let uuid = Uuid::new_v4();
+
+        match client.todo(uuid) {
+            Err(Error(ErrorKind::NotFound(missing), _)) => assert_eq!(uuid, missing),
+            other => panic!("expected NotFound, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_sync() {
+        use std::env;
+        use std::fs;
+        use pomotodo::sync::{SyncEngine, Resolution};
+
+        let server = FakeServer::start().unwrap();
+        let client = server.client();
+        let pomo = server.insert_pomo(Pomo { ..Default::default() });
+        let todo = server.insert_todo(Todo {
+                                          description: "Write docs".to_string(),
+                                          ..Default::default()
+                                      });
+        let uuid = todo.uuid.unwrap();
+
+        let path = env::temp_dir().join(format!("pomotodo-sync-{}.jsonl", Uuid::new_v4()));
+        let mut engine = SyncEngine::open(&path).unwrap();
+        let report = engine.sync(&client).unwrap();
+        assert_eq!(2, report.pulled);
+        assert!(engine.store().pomo(pomo.uuid.unwrap()).is_some());
+        assert!(engine.token().synced_at.is_some());
+
+        engine
+            .edit_todo(uuid, |todo| todo.notice = Some("Locally".to_string()))
+            .unwrap();
+        let patch = TodoPatch {
+            description: Field::Set("Write more docs".to_string()),
+            ..Default::default()
+        };
+        client.update_todo(uuid, &patch).unwrap();
+
+        let local = engine.create_todo(Todo { ..Default::default() }).unwrap();
+        engine
+            .create_sub_todo(local, SubTodo { ..Default::default() })
+            .unwrap();
+        assert_eq!(3, engine.pending());
+
+        let report = engine.sync(&client).unwrap();
+        assert_eq!(3, report.pushed);
+        assert_eq!(1, report.conflicts.len());
+        assert_eq!(Resolution::Merge, report.conflicts[0].1);
+        assert_eq!(0, engine.pending());
+
+        let merged = client.todo(uuid).unwrap();
+        assert_eq!("Write more docs", merged.description);
+        assert_eq!(Some("Locally".to_string()), merged.notice);
+        assert_eq!(2, server.todos().len());
+        assert_eq!(1, server.sub_todos().len());
+        assert!(engine.store().todo(local).is_none());
+
+        engine.delete_todo(uuid).unwrap();
+        engine.sync(&client).unwrap();
+        assert_eq!(1, server.todos().len());
+
+        // Reopening keeps the token.
+        let token = engine.token().clone();
+        drop(engine);
+        assert_eq!(token, *SyncEngine::open(&path).unwrap().token());
+
+        fs::remove_file(&path).unwrap();
+        let mut state = path.into_os_string();
+        state.push(".sync");
+        fs::remove_file(state).unwrap();
+    }
+
+    #[test]
+    fn test_sync_pages() {
+        use std::env;
+        use std::fs;
+        use pomotodo::TodoParameter;
+        use pomotodo::sync::SyncEngine;
+
+        let server = FakeServer::start().unwrap();
+        let client = server.client();
+        for _ in 0..3 {
+            server.insert_todo(Todo { ..Default::default() });
+        }
+
+        let path = env::temp_dir().join(format!("pomotodo-sync-{}.jsonl", Uuid::new_v4()));
+        let mut engine = SyncEngine::open(&path).unwrap();
+        engine.sync(&client).unwrap();
+        assert_eq!(3, engine.store().todos(&TodoParameter::default()).len());
+
+        server.set_page_size(Some(2));
+        let report = engine.sync(&client).unwrap();
+        assert_eq!(0, report.removed);
+        assert_eq!(3, engine.store().todos(&TodoParameter::default()).len());
+
+        let gone = server.todos()[0].uuid.unwrap();
+        client.delete_todo(gone).unwrap();
+        let report = engine.sync(&client).unwrap();
+        assert_eq!(1, report.removed);
+        assert!(engine.store().todo(gone).is_none());
+
+        // A pomo sharing the started_at of the sync token is still pulled.
+        let started_at = Utc.ymd(2017, 1, 1).and_hms(9, 0, 0);
+        server.insert_pomo(Pomo {
+                               started_at: started_at,
+                               ..Default::default()
+                           });
+        assert_eq!(1, engine.sync(&client).unwrap().pulled);
+        let late = server.insert_pomo(Pomo {
+                                          started_at: started_at,
+                                          ..Default::default()
+                                      });
+        assert_eq!(1, engine.sync(&client).unwrap().pulled);
+        assert!(engine.store().pomo(late.uuid.unwrap()).is_some());
+
+        drop(engine);
+        fs::remove_file(&path).unwrap();
+        let mut state = path.into_os_string();
+        state.push(".sync");
+        fs::remove_file(state).unwrap();
+    }
+
+    #[test]
+    fn test_outbox() {
+        use std::env;
+        use std::fs;
+        use pomotodo::outbox::{Outbox, Delivery};
+
+        let server = FakeServer::start().unwrap();
+        let client = server.client();
+        let offline = Client::with_base_url(server.token(), "http://127.0.0.1:1/").unwrap();
+        let path = env::temp_dir().join(format!("pomotodo-outbox-{}.json", Uuid::new_v4()));
+
+        let pomo = Pomo { ..Default::default() };
+        let todo = server.insert_todo(Todo { ..Default::default() });
+        {
+            let mut outbox = Outbox::open(&path).unwrap();
+            assert!(outbox.submit_pomo(&offline, &pomo).unwrap().is_queued());
+            assert!(outbox.submit_pomo(&offline, &pomo).unwrap().is_queued());
+            let patch = TodoPatch {
+                completed: Field::Set(true),
+                ..Default::default()
+            };
+            assert!(outbox.update_todo(&client, todo.uuid.unwrap(), &patch).unwrap().is_queued());
+            assert!(outbox.delete_pomo(&offline, Uuid::new_v4()).unwrap().is_queued());
+            assert!(outbox.bump_todo(&client, todo.uuid.unwrap()).unwrap().is_queued());
+            assert_eq!(4, outbox.len());
+        }
+
+        // The first submission reached server, only its response was lost.
+        server.insert_pomo(pomo.clone());
+
+        let mut outbox = Outbox::open(&path).unwrap();
+        assert_eq!(4, outbox.len());
+        let report = outbox.replay(&offline).unwrap();
+        assert_eq!(0, report.sent);
+        assert_eq!(4, outbox.len());
+
+        let report = outbox.replay(&client).unwrap();
+        assert_eq!(4, report.sent);
+        assert!(report.rejected.is_empty());
+        assert!(outbox.is_empty());
+        assert_eq!(1, server.pomos().len());
+        let todo = client.todo(todo.uuid.unwrap()).unwrap();
+        assert_eq!(Some(true), todo.completed);
+        assert_eq!(Some(1), todo.costed_pomo_count);
+
+        match outbox.submit_pomo(&client, &Pomo { ..Default::default() }).unwrap() {
+            Delivery::Sent(pomo) => assert!(pomo.uuid.is_some()),
+            Delivery::Queued => panic!("expected the pomo to be sent"),
+        }
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_outbox_server_error() {
+        use std::env;
+        use std::fs;
+        use std::sync::atomic::AtomicUsize;
+        use pomotodo::outbox::Outbox;
+        use super::FlakyTransport;
+
+        let server = FakeServer::start().unwrap();
+        let mut builder = Client::builder(server.token());
+        builder.transport(FlakyTransport {
+                              failures: usize::max_value(),
+                              sent: AtomicUsize::new(0),
+                          });
+        let unavailable = builder.finish().unwrap();
+        let path = env::temp_dir().join(format!("pomotodo-outbox-{}.json", Uuid::new_v4()));
+
+        let mut outbox = Outbox::open(&path).unwrap();
+        let pomo = Pomo { ..Default::default() };
+        assert!(outbox.submit_pomo(&unavailable, &pomo).unwrap().is_queued());
+
+        let report = outbox.replay(&unavailable).unwrap();
+        assert_eq!(0, report.sent);
+        assert!(report.rejected.is_empty());
+        assert_eq!(1, Outbox::open(&path).unwrap().len());
+
+        let report = outbox.replay(&server.client()).unwrap();
+        assert_eq!(1, report.sent);
+        assert_eq!(1, server.pomos().len());
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_ical_import() {
+        use pomotodo::RepeatType;
+        use pomotodo::ical::{Calendar, ImportPlan};
+
+        let ics = ["BEGIN:VCALENDAR",
+                   "VERSION:2.0",
+                   "PRODID:-//Example//EN",
+                   "BEGIN:VTODO",
+                   "UID:rent",
+                   "SUMMARY:Pay the ",
+                   " rent\\, again",
+                   "DESCRIPTION:Bank transfer\\nto the landlord",
+                   "DTSTART;TZID=Asia/Shanghai:20170131T090000",
+                   "DUE;TZID=Asia/Shanghai:20170131T100000",
+                   "RRULE:FREQ=MONTHLY;BYMONTHDAY=28,29,30,31;BYSETPOS=-1",
+                   "PRIORITY:1",
+                   "BEGIN:VALARM",
+                   "ACTION:DISPLAY",
+                   "TRIGGER:-PT15M",
+                   "END:VALARM",
+                   "END:VTODO",
+                   "BEGIN:VTODO",
+                   "UID:receipt",
+                   "SUMMARY:Keep the receipt",
+                   "RELATED-TO:rent",
+                   "STATUS:COMPLETED",
+                   "COMPLETED:20170131T020000Z",
+                   "END:VTODO",
+                   "BEGIN:VTODO",
+                   "UID:scan",
+                   "SUMMARY:Scan it",
+                   "RELATED-TO;RELTYPE=PARENT:receipt",
+                   "END:VTODO",
+                   "BEGIN:VTODO",
+                   "UID:orphan",
+                   "SUMMARY:Orphan",
+                   "RELATED-TO:missing",
+                   "END:VTODO",
+                   "BEGIN:VTODO",
+                   "UID:stretch",
+                   "SUMMARY:Stretch",
+                   "DUE:20170102T080000",
+                   "RRULE:FREQ=HOURLY",
+                   "END:VTODO",
+                   "BEGIN:VEVENT",
+                   "UID:docs",
+                   "SUMMARY:Write the docs",
+                   "DTSTART:20170102T090000Z",
+                   "DURATION:PT25M",
+                   "END:VEVENT",
+                   "BEGIN:VEVENT",
+                   "UID:interrupted",
+                   "SUMMARY:Interrupted",
+                   "DTSTART:20170102T100000Z",
+                   "DTEND:20170102T100500Z",
+                   "STATUS:CANCELLED",
+                   "END:VEVENT",
+                   "BEGIN:VEVENT",
+                   "UID:later",
+                   "SUMMARY:Later",
+                   "DTSTART:20990101T090000Z",
+                   "DTEND:20990101T092500Z",
+                   "END:VEVENT",
+                   "BEGIN:VEVENT",
+                   "UID:holiday",
+                   "SUMMARY:Holiday",
+                   "DTSTART;VALUE=DATE:20170101",
+                   "END:VEVENT",
+                   "END:VCALENDAR"]
+            .join("\r\n");
+        let now = Utc.ymd(2017, 6, 1).and_hms(0, 0, 0);
+        let plan = ImportPlan::parse(&ics, Utc, now).unwrap();
+
+        assert_eq!(2, plan.todos.len());
+        let rent = &plan.todos[0];
+        assert_eq!("rent", rent.uid);
+        assert_eq!("Pay the rent, again", rent.todo.description);
+        assert_eq!(Some("Bank transfer\nto the landlord".to_string()), rent.todo.notice);
+        assert_eq!(Some(true), rent.todo.pin);
+        assert_eq!(Some(RepeatType::EachMonth), rent.todo.repeat_type);
+        // 15 minutes before 09:00 in UTC+8.
+        assert_eq!(Some(Utc.ymd(2017, 1, 31).and_hms(0, 45, 0)), rent.todo.remind_time);
+        let sub_todos = rent.sub_todos.iter().map(|s| s.description.as_str()).collect::<Vec<_>>();
+        assert_eq!(vec!["Keep the receipt", "Scan it"], sub_todos);
+        assert_eq!(Some(true), rent.sub_todos[0].completed);
+
+        let stretch = &plan.todos[1].todo;
+        assert_eq!(None, stretch.repeat_type);
+        assert_eq!(Some(Utc.ymd(2017, 1, 2).and_hms(8, 0, 0)), stretch.remind_time);
+
+        assert_eq!(2, plan.pomos.len());
+        assert_eq!(Utc.ymd(2017, 1, 2).and_hms(9, 25, 0), plan.pomos[0].ended_at);
+        assert_eq!(Some(false), plan.pomos[0].abandoned);
+        assert_eq!(Some(true), plan.pomos[1].abandoned);
+        assert!(plan.pomos.iter().all(|pomo| pomo.manual == Some(true)));
 
-    assert_eq!(pomo.description, patched_pomo.description);
+        assert_eq!(2, plan.warnings.len(), "{:?}", plan.warnings);
+        assert!(plan.warnings.iter().any(|warning| warning.contains("Orphan")));
+        assert!(plan.warnings.iter().any(|warning| warning.contains("FREQ=HOURLY")));
 
-    assert!(match sess.delete_pomo(&pomo.uuid.unwrap()) {
-        Ok(_) => true,
-        Err(_) => false,
-    });
+        // Lengths out of range are skipped with a warning rather than panic.
+        let huge = ["BEGIN:VCALENDAR",
+                    "BEGIN:VTODO",
+                    "UID:someday",
+                    "SUMMARY:Someday",
+                    "DUE:20170102T080000Z",
+                    "BEGIN:VALARM",
+                    "TRIGGER:P9999999999D",
+                    "END:VALARM",
+                    "END:VTODO",
+                    "BEGIN:VEVENT",
+                    "UID:forever",
+                    "SUMMARY:Forever",
+                    "DTSTART:20170102T090000Z",
+                    "DURATION:P99999999999999W",
+                    "END:VEVENT",
+                    "END:VCALENDAR"]
+            .join("\r\n");
+        let huge = ImportPlan::parse(&huge, Utc, now).unwrap();
+        assert!(huge.pomos.is_empty());
+        assert_eq!(1, huge.todos.len());
+        assert_eq!(2, huge.warnings.len(), "{:?}", huge.warnings);
+        assert!(huge.warnings.iter().any(|warning| warning.contains("P99999999999999W")));
+        assert!(huge.warnings.iter().any(|warning| warning.contains("P9999999999D")));
 
-    let mut todo = sess.create_todo(&Todo { ..Default::default() }).unwrap();
-    todo.description = "Test todo patch".to_string();
-    let patched_todo = sess.update_todo(&todo).unwrap();
-    assert_eq!(todo.description, patched_todo.description);
+        let server = FakeServer::start().unwrap();
+        let report = plan.apply(&server.client());
+        assert!(report.error.is_none());
+        assert_eq!(2, report.todos.len());
+        assert_eq!(2, report.sub_todos.len());
+        assert_eq!(2, report.pomos.len());
+        assert_eq!(2, server.todos().len());
+        assert_eq!(2, server.pomos().len());
+        assert!(server
+                    .sub_todos()
+                    .iter()
+                    .all(|sub_todo| sub_todo.parent_uuid == report.todos[0].uuid));
 
-    let mut sub_todo = sess.create_subtodo(&todo.uuid.unwrap(), &SubTodo { ..Default::default() })
+        // An exported calendar reads back into the same todos.
+        let sub_todos = server.sub_todos();
+        let mut calendar = Calendar::new();
+        calendar.add_todos(&report.todos).add_sub_todos(&sub_todos);
+        let plan = ImportPlan::parse(&calendar.to_string(), Utc, now).unwrap();
+        assert!(plan.warnings.is_empty(), "{:?}", plan.warnings);
+        assert_eq!(2, plan.todos.len());
+        assert_eq!(report.todos[0].remind_time, plan.todos[0].todo.remind_time);
+        assert_eq!(Some(RepeatType::EachMonth), plan.todos[0].todo.repeat_type);
+        assert_eq!(2, plan.todos[0].sub_todos.len());
+
+        // A failure keeps what was created before it, with the parent of a
+        // failed `SubTodo`.
+        let server = FakeServer::start().unwrap();
+        let mut failing = plan.clone();
+        failing.todos[0].sub_todos[1].description = String::new();
+        let report = failing.apply(&server.client());
+        assert!(report.error.is_some());
+        assert_eq!(1, report.todos.len());
+        assert_eq!(1, report.sub_todos.len());
+        assert_eq!(1, server.todos().len());
+
+        assert!(ImportPlan::parse("not a calendar", Utc, now).is_err());
+        assert!(ImportPlan::parse("BEGIN:VCALENDAR\r\nEND:VTODO\r\n", Utc, now).is_err());
+    }
+}
+
+#[test]
+fn test_rate_limiter() {
+    let limiter = RateLimiter::new(1, 20.0);
+    let shared = limiter.clone();
+
+    let start = Instant::now();
+    limiter.acquire();
+    shared.acquire();
+    limiter.acquire();
+    assert!(start.elapsed() >= Duration::from_millis(90));
+}
+
+#[test]
+fn test_query_urls() {
+    let urls = Arc::new(Mutex::new(Vec::new()));
+    let mut builder = Client::builder("token");
+    builder
+        .base_url("http://localhost/api/")
+        .transport(RecordingTransport { urls: urls.clone() });
+    let client = builder.finish().unwrap();
+
+    let mut pomo_param = PomoParameter::default();
+    pomo_param.with_started_later(Utc.ymd(2017, 1, 1).and_hms(9, 30, 0));
+    client.pomos(pomo_param.clone()).unwrap();
+
+    let mut todo_param = TodoParameter::default();
+    todo_param
+        .with_completed(true)
+        .with_completed_earlier(Utc.ymd(2017, 2, 1).and_hms_milli(0, 0, 0, 500));
+    client.todos(todo_param.clone()).unwrap();
+
+    let pomo_query = "abandoned=false&manual=false&started_later_than=2017-01-01T09%3A30%3A00%2B00%3A00";
+    let todo_query = "completed=true&completed_earlier_than=2017-02-01T00%3A00%3A00.500%2B00%3A00";
+    assert_eq!(pomo_query, pomo_param.to_query());
+    assert_eq!(todo_query, todo_param.to_query());
+    assert_eq!(*urls.lock().unwrap(),
+               vec![format!("http://localhost/api/1/pomos?{}", pomo_query),
+                    format!("http://localhost/api/1/todos?{}", todo_query)]);
+}
+
+#[test]
+fn test_todo_patch() {
+    let patch = TodoPatch {
+        completed: Field::Set(true),
+        remind_time: Field::Clear,
+        ..Default::default()
+    };
+
+    assert_eq!(r#"{"completed":true,"remind_time":null}"#,
+               serde_json::to_string(&patch).unwrap());
+    assert_eq!("{}", serde_json::to_string(&TodoPatch::default()).unwrap());
+}
+
+#[test]
+fn test_validation() {
+    let transport = RecordingTransport::default();
+    let urls = transport.urls.clone();
+    let mut builder = Client::builder("token");
+    builder.transport(transport);
+    let client = builder.finish().unwrap();
+
+    let pomo = Pomo {
+        uuid: Some(Uuid::new_v4()),
+        started_at: Utc.ymd(2017, 1, 1).and_hms(10, 0, 0),
+        ended_at: Utc.ymd(2017, 1, 1).and_hms(9, 35, 0),
+        ..Default::default()
+    };
+    assert_eq!(vec![Violation::NotAllowed("uuid"), Violation::EndedBeforeStarted],
+               pomo.validate());
+
+    match client.submit_pomo(&pomo) {
+        Err(Error(ErrorKind::Invalid(violations), _)) => assert_eq!(pomo.validate(), violations),
+        other => panic!("unexpected result: {:?}", other),
+    }
+
+    let patch = TodoPatch {
+        description: Field::Clear,
+        ..Default::default()
+    };
+    assert!(client.update_todo(Uuid::new_v4(), &patch).is_err());
+    assert!(Todo::default().is_valid());
+    assert!(urls.lock().unwrap().is_empty());
+}
+
+#[test]
+fn test_builders() {
+    let started_at = Utc.ymd(2017, 1, 1).and_hms(9, 0, 0);
+
+    let mut builder = Pomo::builder();
+    builder.started_at(started_at).length(25 * 60).abandoned(true);
+    let pomo = builder.finish().unwrap();
+    assert_eq!(Utc.ymd(2017, 1, 1).and_hms(9, 25, 0), pomo.ended_at);
+    assert_eq!(Some(true), pomo.abandoned);
+
+    let mut builder = Pomo::builder();
+    builder.started_at(started_at);
+    assert!(builder.finish().is_err());
+
+    let mut builder = Pomo::builder();
+    builder
+        .started_at(started_at)
+        .ended_at(started_at - ::chrono::Duration::minutes(1));
+    assert!(builder.finish().is_err());
+
+    let mut builder = Pomo::builder();
+    builder
+        .started_at(started_at)
+        .length(60)
+        .ended_at(started_at + ::chrono::Duration::minutes(25));
+    assert_eq!(Some(25 * 60), builder.finish().unwrap().length);
+
+    let mut builder = Pomo::builder();
+    builder.started_at(started_at).length(u64::max_value());
+    assert!(builder.finish().is_err());
+
+    let mut builder = Todo::builder();
+    builder.description("Todo").pin(true).estimated_pomo_count(2);
+    let todo = builder.finish().unwrap();
+    assert_eq!(Some(true), todo.pin);
+    assert_eq!(Some(2), todo.estimated_pomo_count);
+
+    let mut builder = Todo::builder();
+    builder.description(" ");
+    assert!(builder.finish().is_err());
+
+    let parent = Uuid::new_v4();
+    let mut builder = SubTodo::builder();
+    builder.parent_uuid(parent).completed(true);
+    assert_eq!(Some(parent), builder.finish().unwrap().parent_uuid);
+}
+
+#[test]
+fn test_timer() {
+    use chrono::Duration;
+    use pomotodo::timer::{BreakKind, Event, State, Timer, TimerConfig};
+
+    let start = Utc.ymd(2017, 1, 1).and_hms(9, 0, 0);
+    let mut config = TimerConfig::default();
+    config.with_long_break_every(2);
+    let mut timer = Timer::new(config);
+
+    assert!(timer.pause(start).is_err());
+    timer.start("First", start).unwrap();
+    timer.pause(start + Duration::minutes(10)).unwrap();
+    timer.resume(start + Duration::minutes(15)).unwrap();
+    assert!(timer.tick(start + Duration::minutes(29)).is_none());
+    assert_eq!(Some(Duration::minutes(1)), timer.remaining(start + Duration::minutes(29)));
+
+    match timer.tick(start + Duration::minutes(31)) {
+        Some(Event::Finished(pomo)) => {
+            assert_eq!(start, pomo.started_at);
+            assert_eq!(start + Duration::minutes(30), pomo.ended_at);
+            assert_eq!(Some(30 * 60), pomo.length);
+            assert_eq!(Some(false), pomo.abandoned);
+            assert!(pomo.is_valid());
+        }
+        other => panic!("unexpected event: {:?}", other),
+    }
+
+    timer.start_break(start + Duration::minutes(31)).unwrap();
+    assert_eq!(State::Break(BreakKind::Short), timer.state());
+    assert!(timer.tick(start + Duration::minutes(36)).is_some());
+    assert_eq!(State::Idle, timer.state());
+
+    let start = start + Duration::hours(1);
+    timer.start("Second", start).unwrap();
+    match timer.abandon(start + Duration::minutes(5)).unwrap() {
+        Event::Abandoned(pomo) => assert_eq!(Some(true), pomo.abandoned),
+        other => panic!("unexpected event: {:?}", other),
+    }
+    assert!(timer.start_break(start + Duration::minutes(5)).is_err());
+
+    timer.start("Third", start).unwrap();
+    timer.tick(start + Duration::minutes(25)).unwrap();
+    timer.start_break(start + Duration::minutes(25)).unwrap();
+    assert_eq!(State::Break(BreakKind::Long), timer.state());
+}
+
+#[test]
+fn test_store() {
+    use std::fs;
+    use std::env;
+    use pomotodo::store::Store;
+
+    let path = env::temp_dir().join(format!("pomotodo-store-{}.jsonl", Uuid::new_v4()));
+    let at = |hour| Utc.ymd(2017, 1, 1).and_hms(hour, 0, 0);
+
+    {
+        let mut store = Store::open(&path).unwrap();
+        for hour in 1..6 {
+            let mut builder = Pomo::builder();
+            builder.started_at(at(hour)).length(25 * 60);
+            let mut pomo = builder.finish().unwrap();
+            pomo.uuid = Some(Uuid::new_v4());
+            store.put_pomo(pomo).unwrap();
+        }
+        assert!(store.put_pomo(Pomo::default()).is_err());
+
+        let todo = Todo {
+            uuid: Some(Uuid::new_v4()),
+            completed: Some(true),
+            completed_at: Some(at(3)),
+            ..Default::default()
+        };
+        let parent = todo.uuid.unwrap();
+        store.put_todo(todo).unwrap();
+        let sub_todo = SubTodo {
+            uuid: Some(Uuid::new_v4()),
+            ..Default::default()
+        };
+        let sub_todo_uuid = sub_todo.uuid.unwrap();
+        store.put_sub_todo(parent, sub_todo).unwrap();
+        assert_eq!(1, store.sub_todos(parent).len());
+        assert_eq!(Some(vec![sub_todo_uuid]), store.todo(parent).unwrap().sub_todos);
+
+        let sub_todo = SubTodo {
+            uuid: Some(Uuid::new_v4()),
+            ..Default::default()
+        };
+        let removed = sub_todo.uuid.unwrap();
+        store.put_sub_todo(parent, sub_todo).unwrap();
+        store.remove_sub_todo(removed).unwrap();
+        assert_eq!(Some(vec![sub_todo_uuid]), store.todo(parent).unwrap().sub_todos);
+    }
+
+    let mut store = Store::open(&path).unwrap();
+    let mut param = PomoParameter::default();
+    param.with_manual(true).with_started_later(at(1)).with_started_earlier(at(5));
+    let uuid = {
+        let pomos = store.pomos(&param);
+        assert_eq!(vec![at(4), at(3), at(2)],
+                   pomos.iter().map(|pomo| pomo.started_at).collect::<Vec<_>>());
+        pomos[0].uuid.unwrap()
+    };
+    assert!(store.remove_pomo(uuid).unwrap().is_some());
+    assert!(store.pomo(uuid).is_none());
+
+    let mut param = TodoParameter::default();
+    param.with_completed(true).with_completed_later(at(2));
+    let parent = store.todos(&param)[0].uuid.unwrap();
+    store.remove_todo(parent).unwrap();
+    assert!(store.sub_todos(parent).is_empty());
+
+    store.compact().unwrap();
+    let store = Store::open(&path).unwrap();
+    let mut param = PomoParameter::default();
+    param.with_manual(true);
+    assert_eq!(4, store.pomos(&param).len());
+    assert!(store.todos(&TodoParameter::default()).is_empty());
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_stats() {
+    use chrono::{FixedOffset, NaiveDate};
+    use pomotodo::stats::Stats;
+
+    // In UTC+8, Monday 2 January 07:30 and 09:00, Tuesday 10:00 and Thursday 12:00.
+    let pomos = vec![pomo("Focus", at(1, 1, 23, 30), 25, false),
+                     pomo("Focus", at(1, 2, 1, 0), 25, false),
+                     pomo("Focus", at(1, 3, 2, 0), 25, false),
+                     pomo("Focus", at(1, 5, 3, 0), 25, true),
+                     pomo("Focus", at(1, 5, 4, 0), 25, false)];
+
+    let stats = Stats::new(&pomos, FixedOffset::east(8 * 3600));
+    assert_eq!(5, stats.count());
+    assert_eq!(4, stats.finished());
+    assert_eq!(Some(0.2), stats.abandonment_rate());
+    assert_eq!(100, stats.focus().num_minutes());
+    assert_eq!(Some(25), stats.average_length().map(|length| length.num_minutes()));
+    assert_eq!(2, stats.longest_streak());
+
+    let days = stats.per_day();
+    assert_eq!(3, days.len());
+    assert_eq!(2, days[&NaiveDate::from_ymd(2017, 1, 2)].count);
+    assert_eq!(50, days[&NaiveDate::from_ymd(2017, 1, 2)].focus.num_minutes());
+
+    let weeks = stats.per_week();
+    assert_eq!(1, weeks.len());
+    assert_eq!(4, weeks[&NaiveDate::from_ymd(2017, 1, 2)].count);
+    assert_eq!(4, stats.per_month()[&NaiveDate::from_ymd(2017, 1, 1)].count);
+
+    assert_eq!(1, stats.per_hour()[7]);
+    assert_eq!(1, stats.per_hour()[12]);
+    assert_eq!([2, 1, 0, 1, 0, 0, 0], stats.per_weekday());
+
+    assert_eq!(None, Stats::new(&[], Utc).abandonment_rate());
+}
+
+#[test]
+fn test_tags() {
+    use pomotodo::Tagged;
+    use pomotodo::tags;
+
+    let start = at(1, 2, 9, 0);
+    let pomos = vec![pomo("Write docs #Work #rust", start, 25, false),
+                     pomo("#work Review, #work again", start, 20, false),
+                     pomo("写文档#项目 C# issue#12 ##no (#yes)", start, 10, false),
+                     pomo("No tags at all", start, 25, false)];
+
+    assert_eq!(vec!["work", "rust"], pomos[0].tags());
+    assert_eq!(vec!["work"], pomos[1].tags());
+    assert_eq!(vec!["项目", "yes"], pomos[2].tags());
+    assert!(pomos[3].tags().is_empty());
+
+    assert_eq!("Write docs", pomos[0].description_without_tags());
+    assert_eq!("Review, again", pomos[1].description_without_tags());
+    assert_eq!("写文档 C# issue#12 ##no ( )", pomos[2].description_without_tags());
+    assert!(pomos[0].has_tag("#WORK"));
+
+    assert_eq!(2, tags::with_tags(&pomos, &["work"]).len());
+    assert_eq!(1, tags::with_tags(&pomos, &["#work", "rust"]).len());
+    assert_eq!(4, tags::with_tags(&pomos, &[]).len());
+
+    let todo = Todo {
+        description: "Ship it #release".to_string(),
+        ..Default::default()
+    };
+    assert!(todo.has_tag("release"));
+
+    let per_tag = tags::per_tag(&pomos);
+    let keys = per_tag.keys().map(|tag| tag.as_str()).collect::<Vec<_>>();
+    assert_eq!(vec!["rust", "work", "yes", "项目"], keys);
+    assert_eq!(2, per_tag["work"].count);
+    assert_eq!(45, per_tag["work"].focus.num_minutes());
+}
+
+#[test]
+fn test_repeat() {
+    use chrono::FixedOffset;
+
+    let next = |todo: &Todo, n| todo.occurrences(Utc).unwrap().take(n).collect::<Vec<_>>();
+
+    let daily = todo("Daily", Some(morning(2017, 12, 31)), Some(RepeatType::EachDay));
+    assert_eq!(vec![morning(2018, 1, 1), morning(2018, 1, 2)], next(&daily, 2));
+    let fortnightly = todo("Fortnightly", Some(morning(2017, 1, 1)), Some(RepeatType::EachTwoWeek));
+    assert_eq!(vec![morning(2017, 1, 15), morning(2017, 1, 29)], next(&fortnightly, 2));
+
+    let monthly = todo("Monthly", Some(morning(2016, 1, 31)), Some(RepeatType::EachMonth));
+    assert_eq!(vec![morning(2016, 2, 29), morning(2016, 3, 31), morning(2016, 4, 30)],
+               next(&monthly, 3));
+    let yearly = todo("Yearly", Some(morning(2016, 2, 29)), Some(RepeatType::EachYear));
+    assert_eq!(vec![morning(2017, 2, 28),
+                    morning(2018, 2, 28),
+                    morning(2019, 2, 28),
+                    morning(2020, 2, 29)],
+               next(&yearly, 4));
+
+    // 20:00 on 30 January in UTC is 04:00 on 31 January in UTC+8.
+    let local = todo("Local", Some(at(1, 30, 20, 0)), Some(RepeatType::EachMonth));
+    let first = local.occurrences(FixedOffset::east(8 * 3600)).unwrap().next();
+    assert_eq!(Some(at(2, 27, 20, 0)), first);
+
+    let never = todo("Never", Some(morning(2017, 1, 1)), Some(RepeatType::None));
+    assert!(never.occurrences(Utc).is_none());
+    assert!(Todo::default().occurrences(Utc).is_none());
+
+    let mut weekly = todo("Weekly", Some(morning(2017, 1, 2)), Some(RepeatType::EachWeek));
+    weekly.completed = Some(true);
+    weekly.completed_at = Some(morning(2017, 1, 10));
+    weekly.costed_pomo_count = Some(3);
+    let follow_up = weekly.follow_up(Utc).unwrap();
+    assert_eq!(Some(morning(2017, 1, 16)), follow_up.remind_time);
+    assert_eq!(weekly.description, follow_up.description);
+    assert_eq!(None, follow_up.uuid);
+    assert_eq!(None, follow_up.completed);
+    assert_eq!(None, follow_up.costed_pomo_count);
+    assert!(Todo::default().follow_up(Utc).is_none());
+
+    assert_eq!(Some(RepeatType::EachTwoWeek), "each_two_week".parse::<RepeatType>().ok());
+    assert_eq!("each_month", RepeatType::EachMonth.to_string());
+}
+
+#[test]
+fn test_reminders() {
+    use std::env;
+    use std::fs;
+    use chrono::Duration;
+    use pomotodo::remind::{Reminders, Reminder, Notifier, WebhookNotifier};
+
+    let todos = vec![todo("Once", Some(at(1, 1, 9, 0)), None),
+                     todo("Daily", Some(at(1, 1, 8, 30)), Some(RepeatType::EachDay)),
+                     Todo {
+                         completed: Some(true),
+                         ..todo("Done", Some(at(1, 1, 9, 0)), None)
+                     },
+                     todo("Whenever", None, None)];
+
+    let path = env::temp_dir().join(format!("pomotodo-reminders-{}.json", Uuid::new_v4()));
+    let fired = Arc::new(Mutex::new(Vec::new()));
+    let open = || {
+        let mut reminders = Reminders::open(&path, Utc).unwrap();
+        let fired = fired.clone();
+        reminders.add_notifier(move |reminder: &Reminder| -> Result<(), Error> {
+                                   fired.lock().unwrap().push(reminder.description.clone());
+                                   Ok(())
+                               });
+        reminders.load(&todos);
+        reminders
+    };
+
+    {
+        let mut reminders = open();
+        assert_eq!(2, reminders.fire(at(1, 1, 9, 10)).unwrap().fired.len());
+        assert!(reminders.fire(at(1, 1, 9, 20)).unwrap().fired.is_empty());
+        assert_eq!(Some(at(1, 2, 8, 30)), reminders.next_due(at(1, 1, 9, 20)));
+    }
+    assert_eq!(vec!["Daily", "Once"], *fired.lock().unwrap());
+
+    // A restart fires neither the same reminders again nor stale ones.
+    let mut reminders = open();
+    let due = reminders.fire(at(1, 2, 8, 45)).unwrap().fired;
+    assert_eq!(vec![at(1, 2, 8, 30)], due.iter().map(|r| r.at).collect::<Vec<_>>());
+    assert!(reminders.due(at(1, 5, 12, 0)).is_empty());
+    reminders.with_grace(Duration::days(1));
+    assert_eq!(vec![at(1, 5, 8, 30)],
+               reminders.due(at(1, 5, 12, 0)).iter().map(|r| r.at).collect::<Vec<_>>());
+    assert_eq!(3, fired.lock().unwrap().len());
+
+    let urls = Arc::new(Mutex::new(Vec::new()));
+    let mut webhook = WebhookNotifier::new("http://localhost:9000/remind").unwrap();
+    webhook.transport(RecordingTransport { urls: urls.clone() });
+    webhook.notify(&due[0]).unwrap();
+    assert_eq!(vec!["http://localhost:9000/remind"], *urls.lock().unwrap());
+
+    // A failing notifier neither stops the others nor gets them to notify twice.
+    let mut reminders = open();
+    reminders.add_notifier(|_: &Reminder| -> Result<(), Error> { Err("offline".into()) });
+    let report = reminders.fire(at(1, 3, 8, 45)).unwrap();
+    assert_eq!(1, report.fired.len());
+    assert_eq!(vec![at(1, 3, 8, 30)], report.failed.iter().map(|f| f.0.at).collect::<Vec<_>>());
+    assert_eq!(4, fired.lock().unwrap().len());
+    assert!(reminders.fire(at(1, 3, 8, 50)).unwrap().fired.is_empty());
+    assert_eq!(4, fired.lock().unwrap().len());
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_timezone() {
+    use chrono::NaiveDate;
+    use pomotodo::{Account, Tz};
+    use pomotodo::stats::Stats;
+    use pomotodo::timer::{Event, Timer, TimerConfig};
+
+    let account = |timezone: &str| -> Account {
+        serde_json::from_str(&format!(r#"{{"username": "pomotodo",
+                                           "email": "pomotodo@example.com",
+                                           "timezone": "{}",
+                                           "register_time": "2017-01-01T00:00:00Z",
+                                           "pro_expires_time": "2099-01-01T00:00:00Z"}}"#,
+                                      timezone))
+            .unwrap()
+    };
+    let shanghai = account("Asia/Shanghai");
+    assert_eq!("pomotodo", shanghai.username());
+    assert_eq!("Asia/Shanghai", shanghai.timezone());
+    assert_eq!(Utc.ymd(2099, 1, 1).and_hms(0, 0, 0), shanghai.pro_expires_time());
+    assert_eq!("Asia/Shanghai".parse::<Tz>().unwrap(), shanghai.tz().unwrap());
+    match account("Mars/Olympus_Mons").tz() {
+        Err(Error(ErrorKind::UnknownTimezone(name), _)) => assert_eq!("Mars/Olympus_Mons", name),
+        other => panic!("expected UnknownTimezone, got {:?}", other),
+    }
+
+    // Local times are read whether they carry a `Z`, another offset or none.
+    let pomo: Pomo = serde_json::from_str(r#"{"description": "Local",
+                                              "started_at": "2017-01-01T20:00:00Z",
+                                              "ended_at": "2017-01-01T20:25:00Z",
+                                              "local_started_at": "2017-01-01T23:00:00.000Z",
+                                              "local_ended_at": "2017-01-01T23:25:00"}"#)
         .unwrap();
-    sub_todo.description = "Test sub todo patch".to_string();
-    let patched_sub_todo = sess.update_subtodo(&todo.uuid.unwrap(), &sub_todo).unwrap();
+    let local = NaiveDate::from_ymd(2017, 1, 1).and_hms(23, 0, 0);
+    assert_eq!(Some(local), pomo.local_started_at);
+    assert_eq!(Some(local + chrono::Duration::minutes(25)), pomo.local_ended_at);
+    let json = serde_json::to_value(&pomo).unwrap();
+    assert_eq!("2017-01-01T23:00:00Z", json["local_started_at"]);
+
+    // The recorded local time wins over the account's timezone.
+    assert_eq!(NaiveDate::from_ymd(2017, 1, 1), pomo.local_day(&shanghai).unwrap());
+    let unrecorded = Pomo {
+        local_started_at: None,
+        ..pomo.clone()
+    };
+    assert_eq!(NaiveDate::from_ymd(2017, 1, 2), unrecorded.local_day(&shanghai).unwrap());
+    let pomos = vec![unrecorded];
+    let stats = Stats::for_account(&pomos, &shanghai).unwrap();
+    assert!(stats.per_day().contains_key(&NaiveDate::from_ymd(2017, 1, 2)));
+
+    // New York is on UTC-4 in July.
+    let mut config = TimerConfig::default();
+    config.with_timezone("America/New_York".parse().unwrap());
+    let start = Utc.ymd(2017, 7, 1).and_hms(12, 0, 0);
+    let mut timer = Timer::new(config);
+    timer.start("Summer", start).unwrap();
+    match timer.abandon(start + chrono::Duration::minutes(5)).unwrap() {
+        Event::Abandoned(pomo) => {
+            assert_eq!(Some(NaiveDate::from_ymd(2017, 7, 1).and_hms(8, 0, 0)),
+                       pomo.local_started_at)
+        }
+        other => panic!("expected Abandoned, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_ical_export() {
+    use pomotodo::ical::Calendar;
+
+    let pomo_uuid = Uuid::new_v4();
+    let pomos = vec![Pomo {
+                         uuid: Some(pomo_uuid),
+                         description: "Write; the docs, again".to_string(),
+                         started_at: at(1, 2, 9, 0),
+                         ended_at: at(1, 2, 9, 25),
+                         abandoned: Some(false),
+                         ..Default::default()
+                     },
+                     Pomo {
+                         description: "Interrupted".to_string(),
+                         started_at: at(1, 2, 10, 0),
+                         ended_at: at(1, 2, 10, 5),
+                         abandoned: Some(true),
+                         ..Default::default()
+                     }];
+    let todos = vec![todo("Rent", Some(at(1, 31, 1, 0)), Some(RepeatType::EachMonth)),
+                     todo("Standup", Some(at(1, 2, 1, 0)), Some(RepeatType::EachTwoWeek)),
+                     todo(&"长".repeat(40), None, None)];
+    let sub_todos = vec![SubTodo {
+                             uuid: Some(Uuid::new_v4()),
+                             parent_uuid: todos[1].uuid,
+                             description: "Notes".to_string(),
+                             completed: Some(true),
+                             completed_at: Some(at(1, 2, 2, 0)),
+                             ..Default::default()
+                         }];
+
+    let mut calendar = Calendar::new();
+    calendar.with_name("Pomotodo").add_pomos(&pomos).add_todos(&todos).add_sub_todos(&sub_todos);
+    let ics = calendar.to_string();
+    let lines = ics.split("\r\n").collect::<Vec<_>>();
+
+    assert_eq!(Some(&"BEGIN:VCALENDAR"), lines.first());
+    assert_eq!(vec!["END:VCALENDAR", ""], lines[lines.len() - 2..].to_vec());
+    assert_eq!(2, lines.iter().filter(|line| **line == "BEGIN:VEVENT").count());
+    assert_eq!(4, lines.iter().filter(|line| **line == "BEGIN:VTODO").count());
+    assert!(lines.contains(&format!("UID:{}", pomo_uuid).as_str()));
+    assert!(lines.contains(&"DTSTART:20170102T090000Z"));
+    assert!(lines.contains(&"DTEND:20170102T092500Z"));
+    assert!(lines.contains(&"SUMMARY:Write\\; the docs\\, again"));
+    assert!(lines.contains(&"STATUS:CANCELLED"));
+    assert!(lines.contains(&"CATEGORIES:Pomo,Abandoned"));
+
+    assert!(lines.contains(&"DUE:20170131T010000Z"));
+    assert!(lines.contains(&"RRULE:FREQ=MONTHLY;BYMONTHDAY=28,29,30,31;BYSETPOS=-1"));
+    assert!(lines.contains(&"RRULE:FREQ=WEEKLY;INTERVAL=2"));
+    assert_eq!(2, lines.iter().filter(|line| **line == "BEGIN:VALARM").count());
+    assert!(lines.contains(&format!("RELATED-TO;RELTYPE=PARENT:{}", todos[1].uuid.unwrap())
+                                .as_str()));
+    assert!(lines.contains(&"COMPLETED:20170102T020000Z"));
 
-    assert_eq!(sub_todo.description, patched_sub_todo.description);
+    // Long lines are folded within 75 octets, between characters.
+    assert!(lines.iter().all(|line| line.len() <= 75));
+    let unfolded = ics.replace("\r\n ", "");
+    assert!(unfolded.contains(&format!("SUMMARY:{}\r\n", "长".repeat(40))));
 
-    assert!(match sess.delete_subtodo(&todo.uuid.unwrap(), &sub_todo.uuid.unwrap()) {
-        Ok(_) => true,
-        Err(_) => false,
-    });
+    // 20:00 on 30 January in UTC is 04:00 on 31 January in UTC+8.
+    let todos = vec![todo("Rent", Some(at(1, 30, 20, 0)), Some(RepeatType::EachMonth))];
+    let mut calendar = Calendar::new();
+    calendar.with_timezone("Asia/Shanghai".parse().unwrap()).add_todos(&todos);
+    let ics = calendar.to_string();
+    assert!(ics.contains("\r\nDTSTART;TZID=Asia/Shanghai:20170131T040000\r\n"));
+    assert!(ics.contains("\r\nDUE;TZID=Asia/Shanghai:20170131T040000\r\n"));
+    assert!(ics.contains("\r\nRRULE:FREQ=MONTHLY;BYMONTHDAY=28,29,30,31;BYSETPOS=-1\r\n"));
+    assert!(ics.contains("\r\nBEGIN:VTIMEZONE\r\nTZID:Asia/Shanghai\r\nBEGIN:STANDARD\r\n\
+                          DTSTART:20170101T000000\r\nTZOFFSETFROM:+0800\r\n\
+                          TZOFFSETTO:+0800\r\nEND:STANDARD\r\nEND:VTIMEZONE\r\n"));
 
-    assert!(match sess.delete_todo(&todo.uuid.unwrap()) {
-        Ok(_) => true,
-        Err(_) => false,
-    });
+    // Every daylight saving change is listed for the recurrences.
+    let mut calendar = Calendar::new();
+    calendar.with_timezone("Europe/Berlin".parse().unwrap()).add_todos(&todos);
+    let ics = calendar.to_string();
+    assert!(ics.contains("\r\nDTSTART;TZID=Europe/Berlin:20170130T210000\r\n"));
+    assert!(ics.contains("\r\nBEGIN:DAYLIGHT\r\nDTSTART:20170326T020000\r\n\
+                          TZOFFSETFROM:+0100\r\nTZOFFSETTO:+0200\r\nEND:DAYLIGHT\r\n"));
+    assert!(ics.contains("\r\nBEGIN:STANDARD\r\nDTSTART:20271031T030000\r\n\
+                          TZOFFSETFROM:+0200\r\nTZOFFSETTO:+0100\r\nEND:STANDARD\r\n"));
+    assert_eq!(11, ics.matches("BEGIN:DAYLIGHT").count());
 }